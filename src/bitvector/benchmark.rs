@@ -0,0 +1,116 @@
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::heapsize;
+use crate::malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use crate::report;
+
+use super::Bitvector;
+
+// What rank1 and select1 are checked against.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Oracle {
+    // rank1_simple and select1_simple.
+    Simple,
+    // Counting bits one by one and select1_naive.
+    Naive,
+}
+
+// Benchmarks rank1 and select1 on a random bitvector of len bits, where
+// every bit is set with probability density. Reports one result for each.
+//
+// Returns the number of answers that differ from the oracle, if one is
+// given. The first few mismatches are printed.
+pub fn benchmark_random(
+    len: u64,
+    density: f64,
+    queries: u64,
+    seed: u64,
+    oracle: Option<Oracle>,
+) -> u64 {
+    assert!(len > 0, "bitvector must not be empty");
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let data: Vec<bool> = (0..len).map(|_| rng.gen_bool(density)).collect();
+
+    let start = Instant::now();
+    let bitvector = Bitvector::new(data);
+    let construction = start.elapsed();
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    let space = bitvector.size_of(&mut ops);
+
+    let ones = bitvector.count1();
+
+    let rank_queries: Vec<u64> = (0..queries).map(|_| rng.gen_range(0..=len)).collect();
+    let select_queries: Vec<u64> = match ones {
+        0 => Vec::new(),
+        _ => (0..queries).map(|_| rng.gen_range(1..=ones)).collect(),
+    };
+
+    let mut rank = report::Measurement::new("bitvector-rank1", construction);
+    let mut rank_answers: Vec<u64> = Vec::with_capacity(rank_queries.len());
+    for i in &rank_queries {
+        let start = Instant::now();
+        let got = bitvector.rank1(*i);
+        rank.record_query(start.elapsed());
+
+        rank_answers.push(got);
+    }
+    rank.space = space;
+    report::report(&rank);
+
+    let mut select = report::Measurement::new("bitvector-select1", construction);
+    let mut select_answers: Vec<Option<u64>> = Vec::with_capacity(select_queries.len());
+    for k in &select_queries {
+        let start = Instant::now();
+        let got = bitvector.select1(*k);
+        select.record_query(start.elapsed());
+
+        select_answers.push(got);
+    }
+    select.space = space;
+    report::report(&select);
+
+    let oracle = match oracle {
+        Some(oracle) => oracle,
+        None => return 0,
+    };
+
+    let mut mismatches = 0;
+    let mut mismatch = |what: &str, query: u64, want: String, got: String| {
+        if mismatches < 10 {
+            eprintln!(
+                "Mismatch in {}({}): want: {} got: {}",
+                what, query, want, got
+            );
+        }
+        mismatches += 1;
+    };
+
+    for (i, got) in rank_queries.iter().zip(rank_answers) {
+        let want = match oracle {
+            Oracle::Simple => bitvector.rank1_simple(*i),
+            Oracle::Naive => bitvector.data[..*i as usize].iter().filter(|b| **b).count() as u64,
+        };
+
+        if want != got {
+            mismatch("rank1", *i, want.to_string(), got.to_string());
+        }
+    }
+
+    for (k, got) in select_queries.iter().zip(select_answers) {
+        let want = match oracle {
+            Oracle::Simple => bitvector.select1_simple(*k),
+            Oracle::Naive => bitvector.select1_naive(*k),
+        };
+
+        if want != got {
+            mismatch("select1", *k, format!("{:?}", want), format!("{:?}", got));
+        }
+    }
+
+    mismatches
+}
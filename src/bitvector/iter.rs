@@ -0,0 +1,64 @@
+// Iterates the positions of either all 1s or all 0s of a bitvector.
+//
+// The number of matching bits is known upfront from rank, so the walk stops
+// as soon as the last match was yielded instead of scanning to the end.
+pub struct BitPositions<'a> {
+    data: &'a [bool],
+    // Whether positions of 1s or of 0s are reported.
+    target: bool,
+    position: usize,
+    end: usize,
+    // Matching bits left in [position, end).
+    remaining: u64,
+}
+
+impl<'a> BitPositions<'a> {
+    // count has to be the number of bits equal to target in [start, end).
+    pub fn new(data: &'a [bool], target: bool, start: usize, end: usize, count: u64) -> Self {
+        Self {
+            data,
+            target,
+            position: start,
+            end,
+            remaining: count,
+        }
+    }
+}
+
+impl Iterator for BitPositions<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        // The bits are stored as one bool per byte, so there are no words of
+        // 64 bits to skip at once. Finding the next match with position over
+        // the slice is the closest, it compiles to a byte scan without bounds
+        // checks.
+        let offset = self.data[self.position..self.end]
+            .iter()
+            .position(|&bit| bit == self.target);
+
+        match offset {
+            Some(offset) => {
+                let position = self.position + offset;
+                self.position = position + 1;
+                self.remaining -= 1;
+                Some(position as u64)
+            }
+            None => {
+                // Only reachable when the count passed in was too big.
+                self.position = self.end;
+                None
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+impl ExactSizeIterator for BitPositions<'_> {}
@@ -1,3 +1,5 @@
+mod benchmark;
+mod iter;
 mod rank1;
 mod select1;
 mod sparse_bit_vector;
@@ -5,10 +7,12 @@ mod sparse_bit_vector;
 use std::error::Error;
 use std::fmt;
 
-use super::debug::DEBUG;
+pub use benchmark::*;
+pub use iter::*;
 pub use rank1::*;
 pub use select1::*;
 
+use log::{debug, trace};
 #[allow(unused_imports)]
 use rand::rngs::StdRng;
 #[allow(unused_imports)]
@@ -28,24 +32,12 @@ pub struct Bitvector {
 #[derive(Debug, PartialEq)]
 pub enum MyError {
     InvalidValue,
-    //Select1GotZero,
-    Select1NotEnough1s,
-    Select1OutOfBounds,
-    Select1SuperblockIndexOutOfBounds,
 }
 
 impl fmt::Display for MyError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             MyError::InvalidValue => f.write_str("Invalid value in vector"),
-            // MyError::Select1GotZero => f.write_str("select1 got 0"),
-            MyError::Select1NotEnough1s => f.write_str("select1 not enough 1s"),
-            MyError::Select1OutOfBounds => {
-                f.write_str("select1 out of bounds due to not enough 1s")
-            }
-            MyError::Select1SuperblockIndexOutOfBounds => {
-                f.write_str("select1 superblock index out of bounds")
-            }
         }
     }
 }
@@ -68,9 +60,7 @@ impl Bitvector {
         let select1 = Select1::new(&data, true, false);
         let select0 = Select1::new(&data, false, false);
 
-        if DEBUG {
-            println!("Finished setting up select0 and select1.");
-        }
+        debug!("Finished setting up select0 and select1.");
 
         //println!("Select1-overall: {:?}", select1);
         //println!("Select1-overall: {:#?}", select1);
@@ -84,9 +74,7 @@ impl Bitvector {
             //data: SparseBitVec::from_vec(data),
         };
 
-        if DEBUG {
-            println!("Finished setting up bitvector.");
-        }
+        debug!("Finished setting up bitvector.");
 
         return s;
     }
@@ -105,35 +93,93 @@ impl Bitvector {
         self.rank.rank1_simple(&self.data[..], i)
     }
 
-    pub fn select0(&self, i: u64) -> Result<u64, MyError> {
-        if DEBUG {
-            println!("Select0: {}", i);
-        }
-        self.select0.select_with_boundary_check(&self.data[..], i)
+    // select0/select1 are 1-based: select1(1) is the position of the first
+    // 1 and select1(count) the position of the last one.
+    //
+    // They return None for i == 0 and for i bigger than the number of 0s/1s.
+    // The _simple and _naive variants follow the same contract.
+    pub fn select0(&self, i: u64) -> Option<u64> {
+        trace!("Select0: {}", i);
+
+        self.select0.select(&self.data[..], i)
     }
 
-    pub fn select0_simple(&self, i: u64) -> Result<u64, MyError> {
+    pub fn select0_simple(&self, i: u64) -> Option<u64> {
         self.select0.select_simple(&self.data[..], i)
     }
 
-    pub fn select0_naive(&self, i: u64) -> Result<u64, MyError> {
+    pub fn select0_naive(&self, i: u64) -> Option<u64> {
         self.select0.select_naive(&self.data[..], i)
     }
 
-    pub fn select1(&self, i: u64) -> Result<u64, MyError> {
-        if DEBUG {
-            println!("Select1: {}", i);
-        }
-        self.select1.select_with_boundary_check(&self.data[..], i)
+    pub fn select1(&self, i: u64) -> Option<u64> {
+        trace!("Select1: {}", i);
+
+        self.select1.select(&self.data[..], i)
     }
 
-    pub fn select1_simple(&self, i: u64) -> Result<u64, MyError> {
+    pub fn select1_simple(&self, i: u64) -> Option<u64> {
         self.select1.select_simple(&self.data[..], i)
     }
 
-    pub fn select1_naive(&self, i: u64) -> Result<u64, MyError> {
+    pub fn select1_naive(&self, i: u64) -> Option<u64> {
         self.select1.select_naive(&self.data[..], i)
     }
+
+    // Number of 1s.
+    pub fn count1(&self) -> u64 {
+        self.select1.count()
+    }
+
+    // Number of 0s.
+    pub fn count0(&self) -> u64 {
+        self.select0.count()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // Number of 1s in [a, b).
+    fn count1_in_range(&self, a: u64, b: u64) -> u64 {
+        assert!(
+            a <= b && b <= self.len(),
+            "range {}..{} not inside bitvector of len {}",
+            a,
+            b,
+            self.len()
+        );
+
+        self.rank1(b) - self.rank1(a)
+    }
+
+    // Positions of all 1s, lowest first.
+    pub fn iter_ones(&self) -> BitPositions<'_> {
+        self.ones_in_range(0, self.len())
+    }
+
+    // Positions of all 0s, lowest first.
+    pub fn iter_zeros(&self) -> BitPositions<'_> {
+        self.zeros_in_range(0, self.len())
+    }
+
+    // Positions of the 1s in [a, b), lowest first.
+    pub fn ones_in_range(&self, a: u64, b: u64) -> BitPositions<'_> {
+        let count = self.count1_in_range(a, b);
+
+        BitPositions::new(&self.data[..], true, a as usize, b as usize, count)
+    }
+
+    // Positions of the 0s in [a, b), lowest first.
+    pub fn zeros_in_range(&self, a: u64, b: u64) -> BitPositions<'_> {
+        let count = (b - a) - self.count1_in_range(a, b);
+
+        BitPositions::new(&self.data[..], false, a as usize, b as usize, count)
+    }
 }
 
 fn u64_to_vec_bool(n: u64, bit_size: u64) -> Vec<bool> {
@@ -200,13 +246,12 @@ mod tests {
 
     fn testing_select1_variants<F>(name: &'static str, select1: F)
     where
-        F: Fn(u64) -> Result<u64, MyError>,
+        F: Fn(u64) -> Option<u64>,
     {
         println!("testing select1: {}", name);
 
-        // Except.. the documentation for Elias-Fano (pred)
-        // assumes that select0(0) return 0.
-        assert_eq!(select1(0).unwrap(), 0);
+        // 1-based, so there is no 0th 1.
+        assert_eq!(select1(0), None);
         assert_eq!(select1(1).unwrap(), 0);
         assert_eq!(select1(2).unwrap(), 2);
         assert_eq!(select1(3).unwrap(), 4);
@@ -215,29 +260,21 @@ mod tests {
         assert_eq!(select1(6).unwrap(), 13);
         assert_eq!(select1(7).unwrap(), 14);
         // Only 7 1s in the bitvector.
-        assert_eq!(select1(8).unwrap_err(), MyError::Select1NotEnough1s);
-        assert_eq!(select1(9).unwrap_err(), MyError::Select1NotEnough1s);
-        assert_eq!(select1(10).unwrap_err(), MyError::Select1NotEnough1s);
-        assert_eq!(select1(11).unwrap_err(), MyError::Select1NotEnough1s);
-        assert_eq!(select1(12).unwrap_err(), MyError::Select1NotEnough1s);
-        assert_eq!(select1(13).unwrap_err(), MyError::Select1NotEnough1s);
-        assert_eq!(select1(14).unwrap_err(), MyError::Select1NotEnough1s);
-        assert_eq!(select1(15).unwrap_err(), MyError::Select1NotEnough1s);
-        // Out of bounds of the bitvector, can never be that many 1s.
-        assert_eq!(select1(16).unwrap_err(), MyError::Select1OutOfBounds);
+        for i in 8..20 {
+            assert_eq!(select1(i), None);
+        }
     }
 }
 
 #[allow(dead_code)]
 fn testing_select0_variants<F>(name: &'static str, select0: F)
 where
-    F: Fn(u64) -> Result<u64, MyError>,
+    F: Fn(u64) -> Option<u64>,
 {
     println!("testing select0: {}", name);
 
-    // Except.. the documentation for Elias-Fano (pred)
-    // assumes that select0(0) return 0.
-    assert_eq!(select0(0).unwrap(), 0);
+    // 1-based, so there is no 0th 0.
+    assert_eq!(select0(0), None);
     assert_eq!(select0(1).unwrap(), 1);
     assert_eq!(select0(2).unwrap(), 3);
     assert_eq!(select0(3).unwrap(), 5);
@@ -247,15 +284,10 @@ where
     assert_eq!(select0(7).unwrap(), 11);
     assert_eq!(select0(8).unwrap(), 12);
     assert_eq!(select0(9).unwrap(), 15);
-    // Only 7 1s in the bitvector.
-    assert_eq!(select0(10).unwrap_err(), MyError::Select1NotEnough1s);
-    assert_eq!(select0(11).unwrap_err(), MyError::Select1NotEnough1s);
-    assert_eq!(select0(12).unwrap_err(), MyError::Select1NotEnough1s);
-    assert_eq!(select0(13).unwrap_err(), MyError::Select1NotEnough1s);
-    assert_eq!(select0(14).unwrap_err(), MyError::Select1NotEnough1s);
-    assert_eq!(select0(15).unwrap_err(), MyError::Select1NotEnough1s);
-    // Out of bounds of the bitvector, can never be that many 1s.
-    assert_eq!(select0(16).unwrap_err(), MyError::Select1OutOfBounds);
+    // Only 9 0s in the bitvector.
+    for i in 10..20 {
+        assert_eq!(select0(i), None);
+    }
 }
 
 #[test]
@@ -314,3 +346,230 @@ fn testing_rank1_thorough() {
         assert_eq!(rank1_simple, rank1);
     }
 }
+
+#[test]
+fn testing_iter_ones_zeros_thorough() {
+    let seed = [0; 32];
+    let mut rng = StdRng::from_seed(seed);
+
+    let vec: Vec<bool> = (0..TEST_RANGE_THOROUGH)
+        .map(|_| rng.gen_range(0..2) == 1)
+        .collect();
+
+    let bit_vector = Bitvector::new(vec.clone());
+
+    let ones: Vec<u64> = bit_vector.iter_ones().collect();
+    let zeros: Vec<u64> = bit_vector.iter_zeros().collect();
+
+    assert_eq!(ones.len(), bit_vector.iter_ones().len());
+    assert_eq!(zeros.len(), bit_vector.iter_zeros().len());
+    assert_eq!(ones.len() + zeros.len(), vec.len());
+
+    // select is 1-based, so the k-th 1 is select1(k + 1).
+    for (k, position) in ones.iter().enumerate() {
+        assert_eq!(*position, bit_vector.select1_naive(k as u64 + 1).unwrap());
+    }
+    for (k, position) in zeros.iter().enumerate() {
+        assert_eq!(*position, bit_vector.select0_naive(k as u64 + 1).unwrap());
+    }
+}
+
+#[test]
+fn testing_ones_in_range_thorough() {
+    let seed = [0; 32];
+    let mut rng = StdRng::from_seed(seed);
+
+    let vec: Vec<bool> = (0..TEST_RANGE_THOROUGH)
+        .map(|_| rng.gen_range(0..2) == 1)
+        .collect();
+
+    let bit_vector = Bitvector::new(vec.clone());
+
+    for _ in 0..1000 {
+        let a = rng.gen_range(0..=vec.len());
+        let b = rng.gen_range(a..=vec.len());
+
+        let want_ones: Vec<u64> = (a..b).filter(|i| vec[*i]).map(|i| i as u64).collect();
+        let want_zeros: Vec<u64> = (a..b).filter(|i| !vec[*i]).map(|i| i as u64).collect();
+
+        let got_ones = bit_vector.ones_in_range(a as u64, b as u64);
+        assert_eq!(want_ones.len(), got_ones.len(), "range {}..{}", a, b);
+        assert_eq!(
+            want_ones,
+            got_ones.collect::<Vec<_>>(),
+            "range {}..{}",
+            a,
+            b
+        );
+
+        let got_zeros = bit_vector.zeros_in_range(a as u64, b as u64);
+        assert_eq!(
+            want_zeros,
+            got_zeros.collect::<Vec<_>>(),
+            "range {}..{}",
+            a,
+            b
+        );
+    }
+}
+
+#[cfg(test)]
+type SelectFn = fn(&Bitvector, u64) -> Option<u64>;
+
+// Property: for every bitvector, select1(k) is the position of the k-th 1 for
+// 1 <= k <= #1s and None otherwise. Same for select0 and the 0s.
+#[test]
+fn testing_select_contract_property() {
+    let seed = [0; 32];
+    let mut rng = StdRng::from_seed(seed);
+
+    // Percentage of 1s, including the all-0s and all-1s extremes.
+    let densities = [0, 5, 50, 95, 100];
+
+    // Every small size, plus a few where the subblocks and lookup tables kick
+    // in.
+    for n in (1..64).chain([100, 257, 1000]) {
+        for density in densities {
+            let vec: Vec<bool> = (0..n).map(|_| rng.gen_range(0..100) < density).collect();
+            let bit_vector = Bitvector::new(vec.clone());
+
+            for is1 in [true, false] {
+                let want: Vec<u64> = (0..n as u64).filter(|i| vec[*i as usize] == is1).collect();
+
+                let variants: [(&str, SelectFn); 3] = if is1 {
+                    [
+                        ("select1", Bitvector::select1),
+                        ("select1_simple", Bitvector::select1_simple),
+                        ("select1_naive", Bitvector::select1_naive),
+                    ]
+                } else {
+                    [
+                        ("select0", Bitvector::select0),
+                        ("select0_simple", Bitvector::select0_simple),
+                        ("select0_naive", Bitvector::select0_naive),
+                    ]
+                };
+
+                for (name, select) in variants {
+                    assert_eq!(None, select(&bit_vector, 0), "{} n={} k=0", name, n);
+
+                    for k in 1..=want.len() as u64 + 2 {
+                        let expected = want.get(k as usize - 1).copied();
+                        assert_eq!(expected, select(&bit_vector, k), "{} n={} k={}", name, n, k);
+                    }
+                }
+
+                let count = if is1 {
+                    bit_vector.count1()
+                } else {
+                    bit_vector.count0()
+                };
+                assert_eq!(want.len() as u64, count);
+            }
+        }
+    }
+}
+
+// Synthetic bit i for the large input tests.
+//
+// Cycles through random, sparse and all-1s regions, so select goes through
+// its naive, subblock and lookup table paths. Derived from i alone, so
+// checking does not need a second copy of the data.
+#[allow(dead_code)]
+fn synthetic_bit(i: u64) -> bool {
+    // splitmix64 finalizer.
+    let mut z = i.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    match (i >> 20) % 3 {
+        0 => z & 1 == 1,
+        1 => z.is_multiple_of(1 << 16),
+        _ => true,
+    }
+}
+
+// Builds a synthetic bitvector of n bits and checks rank1 and select0/1 at
+// checkpoints recorded while generating it.
+#[allow(dead_code)]
+fn check_synthetic_bitvector(n: u64) {
+    let step = std::cmp::max(1, n / 10_000);
+    // Also check right around 2^32, where u32 counters would wrap.
+    let boundary: u64 = 1 << 32;
+
+    let mut data: Vec<bool> = Vec::with_capacity(n as usize);
+    let mut rank_checks: Vec<(u64, u64)> = Vec::new();
+    let mut select1_checks: Vec<(u64, u64)> = Vec::new();
+    let mut select0_checks: Vec<(u64, u64)> = Vec::new();
+
+    let mut ones = 0;
+    for i in 0..n {
+        if i % step == 0 || i.abs_diff(boundary) <= 64 {
+            rank_checks.push((i, ones));
+        }
+
+        let bit = synthetic_bit(i);
+        if bit {
+            ones += 1;
+            if ones % step == 0 || ones.abs_diff(boundary) <= 64 {
+                select1_checks.push((ones, i));
+            }
+        } else {
+            let zeros = i + 1 - ones;
+            if zeros % step == 0 || zeros.abs_diff(boundary) <= 64 {
+                select0_checks.push((zeros, i));
+            }
+        }
+
+        data.push(bit);
+    }
+    rank_checks.push((n, ones));
+
+    let zeros = n - ones;
+    let last_one = (0..n).rev().find(|i| data[*i as usize]);
+    let last_zero = (0..n).rev().find(|i| !data[*i as usize]);
+
+    let bit_vector = Bitvector::new(data);
+
+    for (i, want) in rank_checks {
+        assert_eq!(want, bit_vector.rank1(i), "rank1 i={}", i);
+        assert_eq!(i - want, bit_vector.rank0(i), "rank0 i={}", i);
+    }
+    for (k, want) in select1_checks {
+        assert_eq!(Some(want), bit_vector.select1(k), "select1 k={}", k);
+    }
+    for (k, want) in select0_checks {
+        assert_eq!(Some(want), bit_vector.select0(k), "select0 k={}", k);
+    }
+
+    assert_eq!(ones, bit_vector.count1());
+    assert_eq!(zeros, bit_vector.count0());
+    assert_eq!(last_one, bit_vector.select1(ones));
+    assert_eq!(last_zero, bit_vector.select0(zeros));
+    assert_eq!(None, bit_vector.select1(ones + 1));
+    assert_eq!(None, bit_vector.select0(zeros + 1));
+}
+
+#[test]
+fn testing_synthetic_bitvector() {
+    check_synthetic_bitvector(1 << 22);
+}
+
+// Needs tens of GB of memory, so only runs on request:
+//
+//   BITVECTOR_LARGE_BITS=4296015872 cargo test --release -- --ignored beyond_u32
+//
+// Defaults to 2^32 + 2^20 bits.
+#[test]
+#[ignore]
+fn testing_synthetic_bitvector_beyond_u32() {
+    let n = std::env::var("BITVECTOR_LARGE_BITS")
+        .map(|bits| {
+            bits.parse::<u64>()
+                .expect("BITVECTOR_LARGE_BITS is not a number")
+        })
+        .unwrap_or((1 << 32) + (1 << 20));
+
+    check_synthetic_bitvector(n);
+}
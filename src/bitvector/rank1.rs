@@ -1,3 +1,4 @@
+use log::{debug, trace};
 use std::collections::HashMap;
 
 type TupleKey = (Vec<bool>, u64);
@@ -6,8 +7,6 @@ use super::u64_to_vec_bool;
 
 use core::cmp::min;
 
-use super::super::debug::DEBUG;
-
 // Have Rank1 data here to be able to keep initializer here aswell.
 #[derive(MallocSizeOf, Clone)]
 pub struct Rank1 {
@@ -29,7 +28,9 @@ impl Rank1 {
         let n = data.len() as f64;
 
         // Choose block_size much smaller than n.
-        let block_size = (n.log2() / 2.0).floor().round() as u64;
+        //
+        // At least 1 so tiny bitvectors (n < 4) still get blocks.
+        let block_size = ((n.log2() / 2.0).floor().round() as u64).max(1);
 
         // Multiple of block_size.
         let superblock_size = block_size.pow(2) as u64;
@@ -39,14 +40,12 @@ impl Rank1 {
 
         let block_count_per_superblock = block_count / superblock_count + 1;
 
-        if DEBUG {
-            println!(
-            "rank1::new - block_size: {} superblock_size: {} block_count: {} superblock_count: {} block_count_per_superblock: {}",
-            block_size, superblock_size, block_count, superblock_count, block_count_per_superblock
+        debug!(
+        "rank1::new - block_size: {} superblock_size: {} block_count: {} superblock_count: {} block_count_per_superblock: {}",
+        block_size, superblock_size, block_count, superblock_count, block_count_per_superblock
         );
 
-            println!("rank1: Allocating superblock_1s");
-        }
+        debug!("rank1: Allocating superblock_1s");
 
         //
         // Initialize block and superblock arrays.
@@ -55,9 +54,7 @@ impl Rank1 {
         //
         let mut superblock_1s = vec![0u64; (superblock_count + 1) as usize];
 
-        if DEBUG {
-            println!("rank1: Allocating block_1s");
-        }
+        debug!("rank1: Allocating block_1s");
 
         // Takes too much space.
         // Problem: block_count is the number of ALL blocks.
@@ -69,9 +66,7 @@ impl Rank1 {
             (superblock_count + 1) as usize
         ];
 
-        if DEBUG {
-            println!("rank1: Initializing...");
-        }
+        debug!("rank1: Initializing...");
 
         //
         // Calc 1s up to each superblock.
@@ -96,16 +91,14 @@ impl Rank1 {
                 superblock_1s[superblock_index] = rank;
                 superblock_rank = rank;
 
-                if DEBUG {
-                    println!(
-                        "Updating superblock={} i: {} data.len: {} superblock_rank: {} bit: {}",
-                        superblock_index,
-                        i,
-                        data.len(),
-                        superblock_rank,
-                        bit
-                    );
-                }
+                trace!(
+                    "Updating superblock={} i: {} data.len: {} superblock_rank: {} bit: {}",
+                    superblock_index,
+                    i,
+                    data.len(),
+                    superblock_rank,
+                    bit
+                );
             }
 
             // No need to first scope to superblock_size because
@@ -127,17 +120,15 @@ impl Rank1 {
                 // This would mean the last block is not covered, as is the case
                 // in select1.
 
-                if DEBUG {
-                    println!(
-                        "Updating superblock={} block={}: i: {} data.len: {}block_rank: {} bit: {}",
-                        superblock_index,
-                        block_index,
-                        i,
-                        data.len(),
-                        block_rank,
-                        bit
-                    );
-                }
+                trace!(
+                    "Updating superblock={} block={}: i: {} data.len: {}block_rank: {} bit: {}",
+                    superblock_index,
+                    block_index,
+                    i,
+                    data.len(),
+                    block_rank,
+                    bit
+                );
 
                 block_1s[superblock_index][block_index] = block_rank;
             }
@@ -149,6 +140,21 @@ impl Rank1 {
             }
         }
 
+        // Record the end of the data as well, so rank1(data.len()) works.
+        // Only necessary if the loop above did not already start a new
+        // (empty) superblock or block there.
+        let n_usize = data.len();
+        if n_usize.is_multiple_of(superblock_size as usize) {
+            superblock_1s[n_usize / superblock_size as usize] = rank;
+            superblock_rank = rank;
+        }
+        if n_usize.is_multiple_of(block_size as usize) {
+            let superblock_index = n_usize / superblock_size as usize;
+            let block_index = (n_usize % superblock_size as usize) / block_size as usize;
+
+            block_1s[superblock_index][block_index] = rank - superblock_rank;
+        }
+
         // Build rank1 lookup table.
         //
         // But.. maybe using array indexes for lookup is faster though.
@@ -163,12 +169,12 @@ impl Rank1 {
 
         // All possible values for block_size.
         for i in 0..2u64.pow((block_size) as u32) {
-            if DEBUG {
-                println!(
-                    "Creating lookup table block_size={} i={} last_value={}",
-                    block_size, i, last_value
-                );
-            }
+            trace!(
+                "Creating lookup table block_size={} i={} last_value={}",
+                block_size,
+                i,
+                last_value
+            );
 
             // Get block bitvector pattern.
             let block = u64_to_vec_bool(i, block_size);
@@ -242,12 +248,10 @@ impl Rank1 {
         // Account for the last block not being completely filled.
         block_end = min(block_end, data.len());
 
-        if DEBUG {
-            println!(
-            "rank1: i: {} superblock_index: {} block_index: {} block_start: {} block_end: {} superblock_size: {} block_size: {}",
-            i, superblock_index, block_index, block_start, block_end, self.superblock_size, self.block_size
+        trace!(
+        "rank1: i: {} superblock_index: {} block_index: {} block_start: {} block_end: {} superblock_size: {} block_size: {}",
+        i, superblock_index, block_index, block_start, block_end, self.superblock_size, self.block_size
         );
-        }
 
         // Copying might be slow, but current alternative is conversion to
         // u32/64.
@@ -270,16 +274,14 @@ impl Rank1 {
         // because that is dvidable by block_size by definition.
         let lookup = i % self.block_size;
 
-        if DEBUG {
-            println!(
-                "Superblock rank1: {} block-rank1: {} lookup-rank1: {}",
-                self.rank1_superblock_1s[superblock_index],
-                // Does for block_index == 0 this store the rank1 up to the 1st
-                // block, or rather after the 1st block?
-                self.rank1_block_1s[superblock_index][block_index],
-                self.lookup_table_rank1(block, lookup)
-            );
-        }
+        trace!(
+            "Superblock rank1: {} block-rank1: {} lookup-rank1: {}",
+            self.rank1_superblock_1s[superblock_index],
+            // Does for block_index == 0 this store the rank1 up to the 1st
+            // block, or rather after the 1st block?
+            self.rank1_block_1s[superblock_index][block_index],
+            self.lookup_table_rank1(block, lookup)
+        );
 
         // Problem: With i=151 and superblock-size being 151, this somehow
         // still includes the block with block_start from 151 to 164.
@@ -305,12 +307,13 @@ impl Rank1 {
             result = self.rank1_lookup_table[&(filled_block, lookup)] as u64;
         }
 
-        if DEBUG {
-            println!(
-                "Lookup table rank1: block_size: {} block: {:?} lookup: {} -> {}",
-                self.lookup_table_block_size, block, lookup, result
-            );
-        }
+        trace!(
+            "Lookup table rank1: block_size: {} block: {:?} lookup: {} -> {}",
+            self.lookup_table_block_size,
+            block,
+            lookup,
+            result
+        );
 
         return result;
     }
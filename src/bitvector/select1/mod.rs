@@ -4,11 +4,9 @@ mod select_lookup_table;
 use self::select_lookup_table::SelectLookupTable;
 
 use super::u64_to_vec_bool;
-use super::MyError;
+use log::{debug, trace};
 pub use select1_naive::Select1Naive;
 
-use super::super::debug::DEBUG;
-
 #[derive(MallocSizeOf, Clone, Debug)]
 pub struct Select1 {
     select: Select1Internal,
@@ -32,16 +30,29 @@ impl Select1 {
         };
     }
 
-    pub fn select_with_boundary_check(&self, data: &[bool], i: u64) -> Result<u64, MyError> {
-        self.select
-            .select_with_boundary_check(data, &self.lookup_table, i)
+    // Number of ones (zeroes for select0) in data.
+    pub fn count(&self) -> u64 {
+        self.select.k
+    }
+
+    // Position of the i-th one (zero for select0) in data.
+    //
+    // 1-based: select(data, 1) is the first one. Returns None for i == 0 and
+    // for i bigger than the number of ones. All the same for the _naive and
+    // _simple variants.
+    pub fn select(&self, data: &[bool], i: u64) -> Option<u64> {
+        if i == 0 || i > self.count() {
+            return None;
+        }
+
+        Some(self.select.select(data, &self.lookup_table, i))
     }
 
-    pub fn select_naive(&self, data: &[bool], i: u64) -> Result<u64, MyError> {
+    pub fn select_naive(&self, data: &[bool], i: u64) -> Option<u64> {
         self.select.select_naive(data, i)
     }
 
-    pub fn select_simple(&self, data: &[bool], i: u64) -> Result<u64, MyError> {
+    pub fn select_simple(&self, data: &[bool], i: u64) -> Option<u64> {
         self.select.select_simple(data, i)
     }
 }
@@ -52,9 +63,11 @@ struct Select1Internal {
     is1: bool,
 
     // Overall number of zeroes/ones.
-    k: u32,
+    //
+    // u64 because bitvectors can be longer than 2^32 bits.
+    k: u64,
     // Number of zeroes/ones per superblock.
-    b: u32,
+    b: u64,
 
     //todo("implement select1")
     // For each superblock this naive or the sub-blocks with naive or lookup table.
@@ -124,11 +137,11 @@ impl Select1Internal {
 
         let n = data.len();
         // Sum of all zeroes/ones.
-        let k = data.iter().filter(|v| **v == is1).count() as u32;
+        let k = data.iter().filter(|v| **v == is1).count() as u64;
         // Number of zeroes/ones per superblock.
         //
         // Not sure whether floor or ceil or staying float.
-        let b: u32;
+        let b: u64;
 
         if n <= 1 {
             b = 1;
@@ -137,16 +150,14 @@ impl Select1Internal {
                 // let log_n = (n as f64).log2();
                 // b = (log_n * log_n) as u32;
                 //b = (n as f64).log2().powf(2.0).floor() as u32;
-                b = (n as f64).log2().floor() as u32;
+                b = (n as f64).log2().floor() as u64;
             } else {
                 // Calculate b' as Wurzel(log2 n) instead when in the subblock.
-                b = (n as f64).log2().sqrt().floor() as u32;
+                b = (n as f64).log2().sqrt().floor() as u64;
             }
         }
 
-        if DEBUG {
-            println!("{} n={}, k={}, b={}", space(is1, is_subblock), n, k, b);
-        }
+        debug!("{} n={}, k={}, b={}", space(is1, is_subblock), n, k, b);
 
         // Number of superblocks because we have k zeroes/ones
         // which are split of into blocks of b, leaving the resulting #blocks.
@@ -216,9 +227,7 @@ impl Select1Internal {
         // }
 
         if superblock_end < data.len() - 1 {
-            if DEBUG {
-                println!("{} Last: ", space(is1, is_subblock));
-            }
+            debug!("{} Last: ", space(is1, is_subblock));
 
             // Problem: In my case the third is skipped.
 
@@ -252,14 +261,12 @@ impl Select1Internal {
             ));
         }
 
-        if DEBUG {
-            println!(
-                "{} Added superblock_end_indexes b: {} superblock_end_indexes.len(): {} ",
-                space(is1, is_subblock),
-                b,
-                superblock_end_index.len()
-            );
-        }
+        debug!(
+            "{} Added superblock_end_indexes b: {} superblock_end_indexes.len(): {} ",
+            space(is1, is_subblock),
+            b,
+            superblock_end_index.len()
+        );
 
         Self {
             is1: is1,
@@ -279,53 +286,19 @@ impl Select1Internal {
         }
     }
 
-    pub fn select_with_boundary_check(
-        &self,
-        data: &[bool],
-        lookup_table: &SelectLookupTable,
-        i: u64,
-    ) -> Result<u64, MyError> {
-        // Except: i == 1 just means that I want a single 1,
-        // which can with len == 1 mean to return 0, or 1.
-        // self.
-        // Problem: For a sub-select, it is not necessarily an out-of-bounds
-        // for i to be == data.len because prior superblocks already consume
-        // more non-matching size.
-        //
-        // a) Only do this check once on the main level. Recursive calls
-        //    call the down-specced check-version.
-        // b) Return 0 on the sub-level here if i >= self.k.
-        //    This works because self.k only == data.len() if all match.
-        if i >= data.len() as u64 {
-            println!(
-                "{} Accesing i={} in data of len={} data: {:#?}",
-                space(self.is1, self.is_subblock),
-                i,
-                data.len(),
-                data
-            );
-            return Err(MyError::Select1OutOfBounds);
-        }
-
-        return self.select(data, lookup_table, i);
-    }
-
-    pub fn select(
-        &self,
-        data: &[bool],
-        lookup_table: &SelectLookupTable,
-        i: u64,
-    ) -> Result<u64, MyError> {
-        if i == 0 {
-            return Ok(0);
-        }
-
-        if i > self.k as u64 {
-            return Err(MyError::Select1NotEnough1s);
-        }
+    // Expects 1 <= i <= k, which Select1::select checks on the main level.
+    // Recursive calls into subblocks only pass in i inside the subblock.
+    fn select(&self, data: &[bool], lookup_table: &SelectLookupTable, i: u64) -> u64 {
+        assert!(
+            i >= 1 && i <= self.k,
+            "{} select i={} outside of 1..={}",
+            space(self.is1, self.is_subblock),
+            i,
+            self.k
+        );
 
         // Cuts of mantisse, meaning automatic floor() without the rounding up.
-        let superblock_number = i / self.b as u64;
+        let superblock_number = i / self.b;
 
         // Problem: Returns superblock_number=1, naive gets i=3 unchanged.
         // When I change it to i=0 by not subtracting the superblock,
@@ -339,18 +312,14 @@ impl Select1Internal {
         //
         // But restricting it to < ensures the problems is immediately obivous
         // while ensuring I can access the current blocks' end.
-        if superblock_number > self.superblock_end_index.len() as u64 {
-            if DEBUG {
-                println!(
-                    "i: {} b: {} superblock_number: {} superblock_end_index.len(): {}",
-                    i,
-                    self.b,
-                    superblock_number,
-                    self.superblock_end_index.len()
-                );
-            }
-            return Err(MyError::Select1SuperblockIndexOutOfBounds);
-        }
+        assert!(
+            superblock_number <= self.superblock_end_index.len() as u64,
+            "i: {} b: {} superblock_number: {} superblock_end_index.len(): {}",
+            i,
+            self.b,
+            superblock_number,
+            self.superblock_end_index.len()
+        );
 
         let mut previous_superblock_end_index: u64 = 0;
         let this_superblock_start_index: u64;
@@ -375,10 +344,10 @@ impl Select1Internal {
         // return the wrong last 1. Or does that even matter?
         //
         // I currently do not handle i % b == 0, right?
-        if i % self.b as u64 == 0 {
+        if i % self.b == 0 {
             // The i-th 1 is the last 1 in the superblock.
             // So the superblock end is the i-th 1: return it.
-            return Ok(previous_superblock_end_index);
+            return previous_superblock_end_index;
         }
 
         // Add in-superblock depending on naive or  sub-superblocks with (naive or lookup table).
@@ -411,67 +380,61 @@ impl Select1Internal {
             i_excluding_previous_superblocks = i;
         } else {
             // Only count the previous superblocks.
-            i_excluding_previous_superblocks = i - (superblock_number * self.b as u64);
+            i_excluding_previous_superblocks = i - (superblock_number * self.b);
         }
 
-        if DEBUG {
-            println!(
-                "{} superblock_number={} b={} i={} i-inside={}",
-                space(self.is1, self.is_subblock),
-                superblock_number,
-                self.b,
-                i,
-                i_excluding_previous_superblocks
-            );
-        }
+        trace!(
+            "{} superblock_number={} b={} i={} i-inside={}",
+            space(self.is1, self.is_subblock),
+            superblock_number,
+            self.b,
+            i,
+            i_excluding_previous_superblocks
+        );
 
         match self.in_superblock[superblock_number as usize] {
             InSuperblockSelect::Naive(ref naive) => {
                 // What do I pass as i here? 0 == beginning of block.
                 // But if it returns 0,
-                if DEBUG {
-                    println!(
-                        "Naive select super_number={} b={} i={} i-inside={}",
-                        superblock_number, self.b, i, i_excluding_previous_superblocks
-                    );
-                }
-
-                in_block_offset = naive.select(i_excluding_previous_superblocks)?;
-
-                if DEBUG {
-                    println!("returned");
-                }
+                trace!(
+                    "Naive select super_number={} b={} i={} i-inside={}",
+                    superblock_number,
+                    self.b,
+                    i,
+                    i_excluding_previous_superblocks
+                );
+
+                in_block_offset = naive
+                    .select(i_excluding_previous_superblocks)
+                    .expect("naive superblock has fewer ones than counted");
+
+                trace!("returned");
             }
             InSuperblockSelect::Subblock(ref subblock) => {
                 // What data to pass here?
-                if DEBUG {
-                    println!(
-                        "{} Subblock select super_number={} b={} i={} i-inside={}",
-                        space(self.is1, self.is_subblock),
-                        superblock_number,
-                        self.b,
-                        i,
-                        i_excluding_previous_superblocks
-                    );
-                }
+                trace!(
+                    "{} Subblock select super_number={} b={} i={} i-inside={}",
+                    space(self.is1, self.is_subblock),
+                    superblock_number,
+                    self.b,
+                    i,
+                    i_excluding_previous_superblocks
+                );
 
                 in_block_offset = subblock.select(
                     &data[this_superblock_start_index as usize..=this_superblock_end_index],
                     lookup_table,
                     i_excluding_previous_superblocks,
-                )?;
+                );
 
-                if DEBUG {
-                    println!("returned");
-                }
+                trace!("returned");
             }
             InSuperblockSelect::LookupTable => {
                 // What data to pass here?
                 // Need block beginning to end.
                 // And if its the last block, beginning of block to end of
                 // global data.
-                if DEBUG {
-                    println!(
+                trace!(
                     "{} Lookup table select super_number={} b={} i={} i-inside={} from {} to {}",
                     space(self.is1, self.is_subblock),
                     superblock_number,
@@ -481,18 +444,16 @@ impl Select1Internal {
                     this_superblock_start_index,
                     this_superblock_end_index
                 );
-                }
+
                 in_block_offset = lookup_table.lookup(
                     &data[this_superblock_start_index as usize..=this_superblock_end_index],
                     i_excluding_previous_superblocks,
                 );
-                if DEBUG {
-                    println!("returned");
-                }
+                trace!("returned");
             }
         }
 
-        return Ok(this_superblock_start_index + in_block_offset);
+        return this_superblock_start_index + in_block_offset;
     }
 
     fn in_superblock_for(
@@ -523,16 +484,14 @@ impl Select1Internal {
 
             if size as f64 >= (n as f64).log2().powf(4.0) {
                 // Naive.
-                if DEBUG {
-                    println!(
-                    "{} block=naive: superblock_start: {} superblock_end: {} size: {} data.len(): {:}",
-                    space(is1, is_subblock),
-                    superblock_start,
-                    superblock_end,
-                    size,
-                    &data[superblock_start..=superblock_end].len()
+                debug!(
+                "{} block=naive: superblock_start: {} superblock_end: {} size: {} data.len(): {:}",
+                space(is1, is_subblock),
+                superblock_start,
+                superblock_end,
+                size,
+                &data[superblock_start..=superblock_end].len()
                 );
-                }
 
                 result = InSuperblockSelect::Naive(Select1Naive::new(
                     &data[superblock_start..=superblock_end],
@@ -551,12 +510,10 @@ impl Select1Internal {
                 // Ah not that is wrong as well. Its the last main superblock.
 
                 // Subblock.
-                if DEBUG {
-                    println!(
-                    "{} block=subblock: superblock_start: {} superblock_end: {} n: {} b: {} size: {} data: {:?}",
-                    space(is1, is_subblock), superblock_start, superblock_end, n, (n as f32).log2().floor(), size, &data[superblock_start..=superblock_end]
+                debug!(
+                "{} block=subblock: superblock_start: {} superblock_end: {} n: {} b: {} size: {} data: {:?}",
+                space(is1, is_subblock), superblock_start, superblock_end, n, (n as f32).log2().floor(), size, &data[superblock_start..=superblock_end]
                 );
-                }
 
                 result = InSuperblockSelect::Subblock(Select1Internal::new(
                     &data[superblock_start..=superblock_end],
@@ -569,36 +526,33 @@ impl Select1Internal {
             // Naive or lookup table.
             if size as f64 >= (n as f64).log2() {
                 // Naive
-                if DEBUG {
-                    println!(
-                    "{} block=naive: superblock_start: {} superblock_end: {}  size: {} data.len(): {:}",
-                    space(is1, is_subblock),
-                    superblock_start,
-                    superblock_end,
-                    //&data[superblock_start..=superblock_end],
-                    size,
-                    &data[superblock_start..=superblock_end].len(),
+                debug!(
+                "{} block=naive: superblock_start: {} superblock_end: {}  size: {} data.len(): {:}",
+                space(is1, is_subblock),
+                superblock_start,
+                superblock_end,
+                //&data[superblock_start..=superblock_end],
+                size,
+                &data[superblock_start..=superblock_end].len(),
                 );
-                }
 
                 result = InSuperblockSelect::Naive(Select1Naive::new(
                     &data[superblock_start..=superblock_end],
                     is1,
                 ));
             } else {
-                if DEBUG {
-                    println!(
-                    "{} block=lookup_table: superblock_start: {} superblock_end: {} size: {} data: {:?}",
-                    space(is1, is_subblock),
-                    superblock_start,
-                    superblock_end,
-                    size,
-                    &data[superblock_start..=superblock_end]
+                debug!(
+                "{} block=lookup_table: superblock_start: {} superblock_end: {} size: {} data: {:?}",
+                space(is1, is_subblock),
+                superblock_start,
+                superblock_end,
+                size,
+                &data[superblock_start..=superblock_end]
                 );
-                }
+
                 result = InSuperblockSelect::LookupTable;
 
-                lookup_table.encountered((superblock_end - superblock_start + 1) as u32)
+                lookup_table.encountered((superblock_end - superblock_start + 1) as u64)
             }
         }
 
@@ -629,12 +583,9 @@ impl Select1Internal {
     //     }
     // }
 
-    pub fn select_simple(&self, data: &[bool], i: u64) -> Result<u64, MyError> {
+    pub fn select_simple(&self, data: &[bool], i: u64) -> Option<u64> {
         if i == 0 {
-            return Ok(0);
-        }
-        if i >= data.len() as u64 {
-            return Err(MyError::Select1OutOfBounds);
+            return None;
         }
 
         let mut count = 0;
@@ -643,14 +594,14 @@ impl Select1Internal {
                 count += 1;
             }
             if count == i {
-                return Ok(j);
+                return Some(j);
             }
         }
 
-        return Err(MyError::Select1NotEnough1s);
+        return None;
     }
 
-    pub fn select_naive(&self, data: &[bool], i: u64) -> Result<u64, MyError> {
+    pub fn select_naive(&self, data: &[bool], i: u64) -> Option<u64> {
         // Trying naive for whole bitvector.
         let naive = Select1Naive::new(&data[..], self.is1);
 
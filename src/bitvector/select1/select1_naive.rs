@@ -1,19 +1,15 @@
-use super::MyError;
-
 use std::collections::HashMap;
 
-use super::super::super::debug::DEBUG;
-
 // This is inside a block. So have to make index relative to this block.
 #[derive(MallocSizeOf, Clone, Debug)]
 pub struct Select1Naive {
     // size of bitvector
-    n: u32,
+    n: u64,
     // Why do I have b here? Its the superblock #1s, and this here
     // is inside the block.
     //
     // Well, guesss it doesn't matter. Except maybe for performance..
-    b: u32,
+    b: u64,
     // #1s in block. Why is this here?
     // Isn't this redundant with length of answers?
     //
@@ -27,7 +23,9 @@ pub struct Select1Naive {
     // Dunno enough about Rust.
     //
     // Anyway, might choose to use a different hashing algo that is faster.
-    answers: HashMap<u32, u32>,
+    //
+    // u64 for both, because a sparse superblock can span more than 2^32 bits.
+    answers: HashMap<u64, u64>,
 }
 
 impl Select1Naive {
@@ -35,9 +33,9 @@ impl Select1Naive {
         let n = data.len();
         //let k = data.iter().filter(|v| **v == true).count();
         // Not sure whether floor or ceil.
-        let b = (n as f64).log2().powf(2.0).floor() as u32;
+        let b = (n as f64).log2().powf(2.0).floor() as u64;
 
-        let mut answers: HashMap<u32, u32> = HashMap::new();
+        let mut answers: HashMap<u64, u64> = HashMap::new();
 
         let mut count = 0;
         for (i, &val) in data.iter().enumerate() {
@@ -61,37 +59,23 @@ impl Select1Naive {
             // No, because I would store a non-zero value here, so I'd have
             // to special-case in some way.
             count += 1;
-            answers.insert(count, i as u32);
+            answers.insert(count, i as u64);
         }
 
         Self {
-            n: n as u32,
+            n: n as u64,
             b: b,
             //k: k as u32,
             answers: answers,
         }
     }
 
-    pub fn select(&self, i: u64) -> Result<u64, MyError> {
-        if i == 0 {
-            return Ok(0);
-        }
-
-        if i as u32 >= self.n {
-            if DEBUG {
-                println!("Naive: Accessing i={} in select1, but n={}", i, self.n);
-            }
-            return Err(MyError::Select1OutOfBounds);
-        }
-
-        if i as u32 > self.answers.len() as u32 {
-            return Err(MyError::Select1NotEnough1s);
+    // 1-based like Select1::select, None if there is no i-th one.
+    pub fn select(&self, i: u64) -> Option<u64> {
+        if i == 0 || i > self.answers.len() as u64 {
+            return None;
         }
 
-        return self
-            .answers
-            .get(&(i as u32))
-            .ok_or(MyError::Select1OutOfBounds)
-            .map(|v| *v as u64);
+        return self.answers.get(&i).copied();
     }
 }
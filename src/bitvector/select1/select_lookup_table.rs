@@ -1,10 +1,9 @@
 use std::collections::HashMap;
 
 use super::u64_to_vec_bool;
+use log::{debug, trace};
 use std::cmp::max;
 
-use super::super::super::debug::DEBUG;
-
 #[derive(MallocSizeOf, Clone, Debug)]
 pub struct SelectLookupTable {
     lookup_table: HashMap<Vec<bool>, HashMap<u64, u64>>,
@@ -22,7 +21,11 @@ impl SelectLookupTable {
         }
     }
 
-    pub fn encountered(&mut self, lookup_bits: u32) {
+    pub fn encountered(&mut self, lookup_bits: u64) {
+        // Lookup blocks are shorter than log n bits, so even for n beyond
+        // 2^32 this stays tiny. Anything else means the block choice is off.
+        let lookup_bits = u32::try_from(lookup_bits).expect("lookup table block too large");
+
         self.max_lookup_bits = max(self.max_lookup_bits, lookup_bits);
     }
 
@@ -36,14 +39,12 @@ impl SelectLookupTable {
         // This might be too much.. .
         for i in 0..2u64.pow((maximum_block_size_in_bits) as u32) {
             if i % 100 == 0 {
-                if DEBUG {
-                    println!(
-                        "Initializing lookup table for i={} from 0 to 2^{} = {}",
-                        i,
-                        maximum_block_size_in_bits,
-                        2u64.pow(maximum_block_size_in_bits as u32)
-                    );
-                }
+                debug!(
+                    "Initializing lookup table for i={} from 0 to 2^{} = {}",
+                    i,
+                    maximum_block_size_in_bits,
+                    2u64.pow(maximum_block_size_in_bits as u32)
+                );
             }
 
             // Get block bitvector pattern.
@@ -130,12 +131,12 @@ impl SelectLookupTable {
     pub fn lookup(&self, data: &[bool], i: u64) -> u64 {
         // Problem: block with 2 bits is passed in but
         // lookup_table only contains 3-bit blocks to look up.
-        if DEBUG {
-            println!(
-                "lookup_table_select: block={:?} i={} lookup_table: {:#?}",
-                data, i, self.lookup_table
-            );
-        }
+        trace!(
+            "lookup_table_select: block={:?} i={} lookup_table: {:#?}",
+            data,
+            i,
+            self.lookup_table
+        );
 
         if data.len() == self.max_lookup_bits as usize {
             // Block has correct length.
@@ -1,7 +1,22 @@
-pub const DEBUG: bool = false;
+use env_logger::{Builder, Env};
 
-// pub fn debug_println(s: String) {
-//     if DEBUG {
-//         println!("{}", s);
-//     }
-// }
+// Environment variable with the log filter.
+pub const LOG_ENV: &str = "RMQ_LOG";
+
+// Sets up logging to stderr.
+//
+// Filters are env_logger filters: a level (error, warn, info, debug, trace),
+// optionally per module, e.g. `rmq_project::bitvector::rank1=trace,debug`.
+// filter is added on top of RMQ_LOG. Without either, only warnings are
+// logged, or infos if verbose.
+pub fn init(filter: Option<&str>, verbose: bool) {
+    let default = if verbose { "info" } else { "warn" };
+
+    let mut builder = Builder::from_env(Env::default().filter_or(LOG_ENV, default));
+    if let Some(filter) = filter {
+        builder.parse_filters(filter);
+    }
+
+    // Fails only if a logger is set already.
+    let _ = builder.try_init();
+}
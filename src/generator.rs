@@ -0,0 +1,296 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::instances::{LCAInstance, PDInstance, RMQInstance};
+
+// How the numbers of an instance are drawn from [0, max_value].
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ValueDistribution {
+    Uniform,
+    // Dense groups around about sqrt(n) random centers.
+    Clustered,
+    // Uniform, sorted ascending.
+    Sorted,
+    // Strictly decreasing from max_value down to 0, as far as max_value
+    // allows. The minimum of every range is at its right end and the
+    // Cartesian tree degenerates into a path. For PD the numbers cover both
+    // ends of the universe.
+    Adversarial,
+    // Only a handful of distinct values, so nearly every number repeats.
+    Duplicates,
+}
+
+// How the queries are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum QueryDistribution {
+    // RMQ: both ends uniform. PD: uniform in [0, max_value].
+    Uniform,
+    // RMQ: ranges of at most 16 numbers. PD: a number of the instance, off
+    // by at most 2.
+    Short,
+    // RMQ: ranges of at least half of the numbers. PD: uniform over all of
+    // u64, so mostly far above every number.
+    Long,
+}
+
+pub struct GeneratorOptions {
+    pub size: usize,
+    pub queries: usize,
+    // Largest number that is generated.
+    pub max_value: u64,
+    pub values: ValueDistribution,
+    pub query_distribution: QueryDistribution,
+    pub seed: u64,
+}
+
+// Distinct values used by ValueDistribution::Duplicates.
+const DUPLICATE_VALUES: usize = 4;
+
+// Longest range of QueryDistribution::Short.
+const SHORT_RANGE: usize = 16;
+
+fn numbers(options: &GeneratorOptions, rng: &mut StdRng) -> Vec<u64> {
+    let n = options.size;
+    let max_value = options.max_value;
+
+    match options.values {
+        ValueDistribution::Uniform => (0..n).map(|_| rng.gen_range(0..=max_value)).collect(),
+        ValueDistribution::Clustered => {
+            let cluster_count = ((n as f64).sqrt().ceil() as usize).max(1);
+            let centers: Vec<u64> = (0..cluster_count)
+                .map(|_| rng.gen_range(0..=max_value))
+                .collect();
+
+            // Clusters together cover about a hundredth of the universe.
+            let width = (max_value / (100 * cluster_count as u64)).max(1);
+
+            (0..n)
+                .map(|_| {
+                    let center = *centers.choose(rng).unwrap();
+                    center
+                        .saturating_sub(width / 2)
+                        .saturating_add(rng.gen_range(0..=width))
+                        .min(max_value)
+                })
+                .collect()
+        }
+        ValueDistribution::Sorted => {
+            let mut numbers: Vec<u64> = (0..n).map(|_| rng.gen_range(0..=max_value)).collect();
+            numbers.sort_unstable();
+            numbers
+        }
+        ValueDistribution::Adversarial => {
+            let last = n.max(2) as u128 - 1;
+
+            (0..n as u128)
+                .map(|i| ((last - i.min(last)) * max_value as u128 / last) as u64)
+                .collect()
+        }
+        ValueDistribution::Duplicates => {
+            let values: Vec<u64> = (0..DUPLICATE_VALUES)
+                .map(|_| rng.gen_range(0..=max_value))
+                .collect();
+
+            (0..n).map(|_| *values.choose(rng).unwrap()).collect()
+        }
+    }
+}
+
+fn rmq_query(options: &GeneratorOptions, rng: &mut StdRng) -> (usize, usize) {
+    let n = options.size;
+
+    match options.query_distribution {
+        QueryDistribution::Uniform => {
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n);
+            (a.min(b), a.max(b))
+        }
+        QueryDistribution::Short => {
+            let len = rng.gen_range(1..=SHORT_RANGE.min(n));
+            let from = rng.gen_range(0..=n - len);
+            (from, from + len - 1)
+        }
+        QueryDistribution::Long => {
+            let len = rng.gen_range(n.div_ceil(2)..=n);
+            let from = rng.gen_range(0..=n - len);
+            (from, from + len - 1)
+        }
+    }
+}
+
+fn pd_query(options: &GeneratorOptions, numbers: &[u64], rng: &mut StdRng) -> u64 {
+    match options.query_distribution {
+        QueryDistribution::Uniform => rng.gen_range(0..=options.max_value),
+        QueryDistribution::Short => {
+            let number = *numbers.choose(rng).unwrap();
+            let offset = rng.gen_range(0..=4);
+            number.saturating_add(offset).saturating_sub(2)
+        }
+        QueryDistribution::Long => rng.gen(),
+    }
+}
+
+// Same options and seed give the same instance.
+pub fn generate_pd(options: &GeneratorOptions) -> PDInstance {
+    assert!(options.size > 0, "instance needs at least one number");
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let numbers = numbers(options, &mut rng);
+    let queries = (0..options.queries)
+        .map(|_| pd_query(options, &numbers, &mut rng))
+        .collect();
+
+    PDInstance { numbers, queries }
+}
+
+// Same options and seed give the same instance.
+pub fn generate_rmq(options: &GeneratorOptions) -> RMQInstance {
+    assert!(options.size > 0, "instance needs at least one number");
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let numbers = numbers(options, &mut rng);
+    let queries = (0..options.queries)
+        .map(|_| rmq_query(options, &mut rng))
+        .collect();
+
+    RMQInstance { numbers, queries }
+}
+
+// A random recursive tree on size nodes with root 0: every other node
+// hangs below a uniformly chosen earlier one, so the depth is about log n.
+// ValueDistribution::Adversarial gives a path instead. Queries are uniform
+// pairs of nodes, the other options are ignored.
+pub fn generate_lca(options: &GeneratorOptions) -> LCAInstance {
+    assert!(options.size > 0, "instance needs at least one node");
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let n = options.size;
+
+    let parents = (0..n as u64)
+        .map(|i| match (i, options.values) {
+            (0, _) => 0,
+            (_, ValueDistribution::Adversarial) => i - 1,
+            _ => rng.gen_range(0..i),
+        })
+        .collect();
+    let queries = (0..options.queries)
+        .map(|_| (rng.gen_range(0..n), rng.gen_range(0..n)))
+        .collect();
+
+    LCAInstance { parents, queries }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::instances;
+
+    fn options(
+        values: ValueDistribution,
+        query_distribution: QueryDistribution,
+    ) -> GeneratorOptions {
+        GeneratorOptions {
+            size: 500,
+            queries: 300,
+            max_value: 10_000,
+            values,
+            query_distribution,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn testing_generator_distributions() {
+        let values = [
+            ValueDistribution::Uniform,
+            ValueDistribution::Clustered,
+            ValueDistribution::Sorted,
+            ValueDistribution::Adversarial,
+            ValueDistribution::Duplicates,
+        ];
+        let query_distributions = [
+            QueryDistribution::Uniform,
+            QueryDistribution::Short,
+            QueryDistribution::Long,
+        ];
+
+        for values in values {
+            for query_distribution in query_distributions {
+                let options = options(values, query_distribution);
+                let instance = generate_rmq(&options);
+
+                assert_eq!(500, instance.numbers.len());
+                assert!(instance.numbers.iter().all(|v| *v <= 10_000));
+                assert_eq!(300, instance.queries.len());
+
+                for (from, to) in &instance.queries {
+                    assert!(from <= to && *to < 500, "{:?} {:?}", values, (from, to));
+
+                    match query_distribution {
+                        QueryDistribution::Short => assert!(to - from < SHORT_RANGE),
+                        QueryDistribution::Long => assert!(to - from + 1 >= 250),
+                        QueryDistribution::Uniform => {}
+                    }
+                }
+
+                let pd = generate_pd(&options);
+                assert_eq!(instance.numbers, pd.numbers);
+                assert_eq!(300, pd.queries.len());
+            }
+        }
+
+        let sorted = generate_rmq(&options(
+            ValueDistribution::Sorted,
+            QueryDistribution::Uniform,
+        ));
+        assert!(sorted.numbers.windows(2).all(|w| w[0] <= w[1]));
+
+        let adversarial = generate_rmq(&options(
+            ValueDistribution::Adversarial,
+            QueryDistribution::Uniform,
+        ));
+        assert!(adversarial.numbers.windows(2).all(|w| w[0] > w[1]));
+        assert_eq!(10_000, adversarial.numbers[0]);
+
+        let mut duplicates = generate_rmq(&options(
+            ValueDistribution::Duplicates,
+            QueryDistribution::Uniform,
+        ))
+        .numbers;
+        duplicates.sort_unstable();
+        duplicates.dedup();
+        assert!(duplicates.len() <= DUPLICATE_VALUES);
+    }
+
+    #[test]
+    fn testing_generator_seed_and_roundtrip() {
+        let options = options(ValueDistribution::Clustered, QueryDistribution::Short);
+
+        let a = generate_rmq(&options);
+        let b = generate_rmq(&options);
+        assert_eq!(a.numbers, b.numbers);
+        assert_eq!(a.queries, b.queries);
+
+        let other = generate_rmq(&GeneratorOptions { seed: 8, ..options });
+        assert_ne!(a.numbers, other.numbers);
+
+        let path = std::env::temp_dir().join(format!(
+            "rmq_project_generated_rmq_{}.txt",
+            std::process::id()
+        ));
+        instances::write_rmq_instance(&path, &a).unwrap();
+        let read = instances::read_rmq_instance(&path).unwrap();
+        assert_eq!(a.numbers, read.numbers);
+        assert_eq!(a.queries, read.queries);
+
+        let pd = generate_pd(&options);
+        instances::write_pd_instance(&path, &pd).unwrap();
+        let read = instances::read_pd_instance(&path).unwrap();
+        assert_eq!(pd.numbers, read.numbers);
+        assert_eq!(pd.queries, read.queries);
+    }
+}
@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::InstanceError;
+
+// Binary instance format, all values are little-endian u64:
+//
+//   magic | kind | number count | numbers | query count | queries
+//
+// A PD query is one value, an RMQ query is the two values from and to, an
+// LCA query the two nodes u and v.
+// Text instances start with a digit, so the magic can never be mistaken
+// for one.
+pub const MAGIC: &[u8; 8] = b"INSTBIN\x01";
+
+pub const KIND_PD: u64 = 1;
+pub const KIND_RMQ: u64 = 2;
+pub const KIND_LCA: u64 = 3;
+
+fn kind_name(kind: u64) -> &'static str {
+    match kind {
+        KIND_PD => "pd",
+        KIND_RMQ => "rmq",
+        KIND_LCA => "lca",
+        _ => "unknown",
+    }
+}
+
+// Whether the reader is at the start of a binary instance. Nothing is
+// consumed.
+pub fn is_binary(reader: &mut impl BufRead) -> io::Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(MAGIC))
+}
+
+pub struct BinaryReader<R> {
+    path: PathBuf,
+    reader: R,
+    // Bytes read so far, for error messages.
+    offset: u64,
+}
+
+impl<R: Read> BinaryReader<R> {
+    // what names the part of the file being read if it ends early.
+    pub fn read_u64(&mut self, what: &str) -> Result<u64, InstanceError> {
+        let mut bytes = [0; 8];
+
+        self.reader
+            .read_exact(&mut bytes)
+            .map_err(|error| match error.kind() {
+                io::ErrorKind::UnexpectedEof => {
+                    self.invalid(self.offset, format!("file ends inside {}", what))
+                }
+                _ => InstanceError::Io {
+                    path: self.path.clone(),
+                    error,
+                },
+            })?;
+        self.offset += 8;
+
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // Error about the value starting at offset.
+    pub fn invalid(&self, offset: u64, reason: String) -> InstanceError {
+        InstanceError::InvalidBinary {
+            path: self.path.clone(),
+            offset,
+            reason,
+        }
+    }
+}
+
+// Reads everything up to the queries.
+//
+// Returns the reader positioned at the first query, the numbers and the
+// number of queries.
+pub fn read_header<R: Read>(
+    path: &Path,
+    reader: R,
+    kind: u64,
+) -> Result<(BinaryReader<R>, Vec<u64>, u64), InstanceError> {
+    let mut reader = BinaryReader {
+        path: path.to_path_buf(),
+        reader,
+        offset: 0,
+    };
+
+    let mut magic = [0; 8];
+    reader
+        .reader
+        .read_exact(&mut magic)
+        .map_err(|error| InstanceError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    if &magic != MAGIC {
+        return Err(reader.invalid(0, "not a binary instance".to_string()));
+    }
+    reader.offset = MAGIC.len() as u64;
+
+    let kind_offset = reader.offset();
+    let found_kind = reader.read_u64("the header")?;
+    if found_kind != kind {
+        return Err(reader.invalid(
+            kind_offset,
+            format!(
+                "expected {} instance, got {} instance",
+                kind_name(kind),
+                kind_name(found_kind)
+            ),
+        ));
+    }
+
+    let number_count = reader.read_u64("the header")?;
+
+    // Cap the capacity, a corrupt count should fail with an error below and
+    // not with an allocation failure here.
+    let mut numbers: Vec<u64> = Vec::with_capacity(number_count.min(1 << 20) as usize);
+    for _ in 0..number_count {
+        numbers.push(reader.read_u64("the numbers")?);
+    }
+
+    let query_count = reader.read_u64("the query count")?;
+
+    Ok((reader, numbers, query_count))
+}
+
+pub fn read_pd_query<R: Read>(
+    reader: &mut BinaryReader<R>,
+    _numbers_count: usize,
+) -> Result<u64, InstanceError> {
+    reader.read_u64("the queries")
+}
+
+pub fn read_rmq_query<R: Read>(
+    reader: &mut BinaryReader<R>,
+    numbers_count: usize,
+) -> Result<(usize, usize), InstanceError> {
+    let offset = reader.offset();
+    let from = reader.read_u64("the queries")?;
+    let to = reader.read_u64("the queries")?;
+
+    if from > to || to >= numbers_count as u64 {
+        return Err(reader.invalid(
+            offset,
+            format!(
+                "query {},{} is not a range inside {} numbers",
+                from, to, numbers_count
+            ),
+        ));
+    }
+
+    Ok((from as usize, to as usize))
+}
+
+pub fn read_lca_query<R: Read>(
+    reader: &mut BinaryReader<R>,
+    nodes_count: usize,
+) -> Result<(usize, usize), InstanceError> {
+    let offset = reader.offset();
+    let u = reader.read_u64("the queries")?;
+    let v = reader.read_u64("the queries")?;
+
+    if u >= nodes_count as u64 || v >= nodes_count as u64 {
+        return Err(reader.invalid(
+            offset,
+            format!(
+                "query {},{} has nodes outside of {} nodes",
+                u, v, nodes_count
+            ),
+        ));
+    }
+
+    Ok((u as usize, v as usize))
+}
+
+pub struct BinaryWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    // Where the query count goes once it is known.
+    query_count_offset: u64,
+    query_count: u64,
+}
+
+impl BinaryWriter {
+    pub fn create(path: &Path, kind: u64, numbers: &[u64]) -> Result<Self, InstanceError> {
+        let file = File::create(path).map_err(|error| InstanceError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+        let mut writer = BinaryWriter {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            query_count_offset: (MAGIC.len() + 16 + 8 * numbers.len()) as u64,
+            query_count: 0,
+        };
+
+        writer.write_bytes(MAGIC)?;
+        writer.write_u64(kind)?;
+        writer.write_u64(numbers.len() as u64)?;
+        for number in numbers {
+            writer.write_u64(*number)?;
+        }
+        // Placeholder, patched in finish.
+        writer.write_u64(0)?;
+
+        Ok(writer)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), InstanceError> {
+        self.writer
+            .write_all(bytes)
+            .map_err(|error| InstanceError::Io {
+                path: self.path.clone(),
+                error,
+            })
+    }
+
+    fn write_u64(&mut self, value: u64) -> Result<(), InstanceError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    pub fn write_query(&mut self, values: &[u64]) -> Result<(), InstanceError> {
+        for value in values {
+            self.write_u64(*value)?;
+        }
+        self.query_count += 1;
+
+        Ok(())
+    }
+
+    // Writes the query count. Returns the number of queries written.
+    pub fn finish(self) -> Result<u64, InstanceError> {
+        let path = self.path;
+        let io_error = |error| InstanceError::Io {
+            path: path.clone(),
+            error,
+        };
+
+        let mut file = self
+            .writer
+            .into_inner()
+            .map_err(|error| io_error(error.into_error()))?;
+
+        file.seek(SeekFrom::Start(self.query_count_offset))
+            .map_err(io_error)?;
+        file.write_all(&self.query_count.to_le_bytes())
+            .map_err(io_error)?;
+
+        Ok(self.query_count)
+    }
+}
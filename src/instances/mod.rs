@@ -0,0 +1,1127 @@
+mod binary;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+use log::debug;
+
+#[allow(dead_code)]
+pub struct PDInstance {
+    pub numbers: Vec<u64>,
+    pub queries: Vec<u64>,
+}
+
+pub struct RMQInstance {
+    pub numbers: Vec<u64>,
+    pub queries: Vec<(usize, usize)>,
+}
+
+// A rooted tree given by the parent of every node, the root is its own
+// parent. Queries are pairs of nodes.
+pub struct LCAInstance {
+    pub parents: Vec<u64>,
+    pub queries: Vec<(usize, usize)>,
+}
+
+// Matrix of rows x cols numbers, row by row. Queries are rectangles
+// (row_from, col_from, row_to, col_to), both corners inclusive.
+pub struct MatrixInstance {
+    pub rows: usize,
+    pub cols: usize,
+    pub numbers: Vec<u64>,
+    pub queries: Vec<(usize, usize, usize, usize)>,
+}
+
+// Line numbers are 1-based, like in an editor.
+#[derive(Debug)]
+pub enum InstanceError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    // First line is not the number of numbers.
+    InvalidCount {
+        path: PathBuf,
+        line: usize,
+        text: String,
+    },
+    InvalidNumber {
+        path: PathBuf,
+        line: usize,
+        text: String,
+    },
+    // RMQ query that is not `from,to`.
+    InvalidQuery {
+        path: PathBuf,
+        line: usize,
+        text: String,
+    },
+    // LCA query that is not `u,v` with two nodes of the tree.
+    InvalidNodes {
+        path: PathBuf,
+        line: usize,
+        text: String,
+        nodes_count: usize,
+    },
+    // Parents that do not form a rooted tree.
+    InvalidTree {
+        path: PathBuf,
+        reason: String,
+    },
+    // First line of a matrix instance that is not `rows,cols`.
+    InvalidDimensions {
+        path: PathBuf,
+        text: String,
+    },
+    // Matrix row that is not cols comma-separated numbers.
+    InvalidRow {
+        path: PathBuf,
+        line: usize,
+        text: String,
+        cols: usize,
+    },
+    // Matrix query that is not `row_from,col_from,row_to,col_to` with both
+    // corners inside the matrix and the first above and left of the second.
+    InvalidRectangle {
+        path: PathBuf,
+        line: usize,
+        text: String,
+        rows: usize,
+        cols: usize,
+    },
+    // RMQ query with to < from or to outside of the numbers.
+    QueryOutOfRange {
+        path: PathBuf,
+        line: usize,
+        text: String,
+        numbers_count: usize,
+    },
+    // File ended before all announced numbers were read.
+    MissingNumbers {
+        path: PathBuf,
+        expected: u64,
+        found: u64,
+    },
+    // Anything wrong in a binary instance, at the given byte offset.
+    InvalidBinary {
+        path: PathBuf,
+        offset: u64,
+        reason: String,
+    },
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstanceError::Io { path, error } => {
+                write!(f, "{}: {}", path.display(), error)
+            }
+            InstanceError::InvalidCount { path, line, text } => write!(
+                f,
+                "{}:{}: expected number of numbers, got {:?}",
+                path.display(),
+                line,
+                text
+            ),
+            InstanceError::InvalidNumber { path, line, text } => write!(
+                f,
+                "{}:{}: expected unsigned number, got {:?}",
+                path.display(),
+                line,
+                text
+            ),
+            InstanceError::InvalidQuery { path, line, text } => write!(
+                f,
+                "{}:{}: expected query `from,to`, got {:?}",
+                path.display(),
+                line,
+                text
+            ),
+            InstanceError::InvalidNodes {
+                path,
+                line,
+                text,
+                nodes_count,
+            } => write!(
+                f,
+                "{}:{}: expected query `u,v` with nodes below {}, got {:?}",
+                path.display(),
+                line,
+                nodes_count,
+                text
+            ),
+            InstanceError::InvalidTree { path, reason } => {
+                write!(f, "{}: not a rooted tree: {}", path.display(), reason)
+            }
+            InstanceError::InvalidDimensions { path, text } => write!(
+                f,
+                "{}:1: expected matrix size `rows,cols`, got {:?}",
+                path.display(),
+                text
+            ),
+            InstanceError::InvalidRow {
+                path,
+                line,
+                text,
+                cols,
+            } => write!(
+                f,
+                "{}:{}: expected row of {} comma-separated numbers, got {:?}",
+                path.display(),
+                line,
+                cols,
+                text
+            ),
+            InstanceError::InvalidRectangle {
+                path,
+                line,
+                text,
+                rows,
+                cols,
+            } => write!(
+                f,
+                "{}:{}: expected query `row_from,col_from,row_to,col_to` inside {}x{} numbers, got {:?}",
+                path.display(),
+                line,
+                rows,
+                cols,
+                text
+            ),
+            InstanceError::QueryOutOfRange {
+                path,
+                line,
+                text,
+                numbers_count,
+            } => write!(
+                f,
+                "{}:{}: query {:?} is not a range inside {} numbers",
+                path.display(),
+                line,
+                text,
+                numbers_count
+            ),
+            InstanceError::MissingNumbers {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: expected {} numbers, file ends after {}",
+                path.display(),
+                expected,
+                found
+            ),
+            InstanceError::InvalidBinary {
+                path,
+                offset,
+                reason,
+            } => write!(f, "{}: byte {}: {}", path.display(), offset, reason),
+        }
+    }
+}
+
+impl Error for InstanceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InstanceError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Reads the numbers part shared by all instances: the count on the first
+// line, followed by one number per line.
+//
+// Returns the numbers and the number of lines read so far.
+fn read_numbers(
+    path: &Path,
+    reader: &mut impl BufRead,
+) -> Result<(Vec<u64>, usize), InstanceError> {
+    let io_error = |error| InstanceError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut number_count_string = String::new();
+    reader
+        .read_line(&mut number_count_string)
+        .map_err(io_error)?;
+
+    debug!("number_count_string: {}", number_count_string);
+
+    // Trim to avoid newline.
+    let number_count =
+        number_count_string
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| InstanceError::InvalidCount {
+                path: path.to_path_buf(),
+                line: 1,
+                text: number_count_string.trim().to_string(),
+            })?;
+
+    // Capped like in binary::read_header, a huge count has to end in
+    // MissingNumbers and not in an allocation failure.
+    let mut numbers: Vec<u64> = Vec::with_capacity(number_count.min(1 << 20) as usize);
+    let mut line_buffer = String::new();
+    let mut line = 1;
+
+    for found in 0..number_count {
+        line_buffer.clear();
+        if reader.read_line(&mut line_buffer).map_err(io_error)? == 0 {
+            return Err(InstanceError::MissingNumbers {
+                path: path.to_path_buf(),
+                expected: number_count,
+                found,
+            });
+        }
+        line += 1;
+
+        // Trim to avoid newline.
+        let number =
+            line_buffer
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| InstanceError::InvalidNumber {
+                    path: path.to_path_buf(),
+                    line,
+                    text: line_buffer.trim().to_string(),
+                })?;
+
+        numbers.push(number);
+    }
+
+    Ok((numbers, line))
+}
+
+type Reader = Box<dyn BufRead>;
+
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// Opens an instance file, decompressing gzip and zstd on the fly.
+//
+// Compression is detected by the magic bytes, falling back to the .gz and
+// .zst extensions. A file with the extension but without the magic then
+// fails with the decoder's error instead of being read as plain text.
+// Everything after this only sees the decompressed bytes, so text and binary
+// instances can both be compressed.
+fn open(path: &Path) -> Result<Reader, InstanceError> {
+    let io_error = |error| InstanceError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut reader = io::BufReader::new(File::open(path).map_err(io_error)?);
+
+    let head = reader.fill_buf().map_err(io_error)?;
+
+    let compression = if head.starts_with(GZIP_MAGIC) {
+        Some(Compression::Gzip)
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Some(Compression::Zstd)
+    } else {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Some(Compression::Gzip),
+            Some("zst") => Some(Compression::Zstd),
+            _ => None,
+        }
+    };
+
+    match compression {
+        Some(Compression::Gzip) => Ok(Box::new(io::BufReader::new(MultiGzDecoder::new(reader)))),
+        Some(Compression::Zstd) => {
+            let decoder = zstd::stream::read::Decoder::with_buffer(reader).map_err(io_error)?;
+            Ok(Box::new(io::BufReader::new(decoder)))
+        }
+        None => Ok(Box::new(reader)),
+    }
+}
+
+// Numbers of an instance, with the queries still to be read.
+//
+// Only the numbers are held in memory. The queries are parsed one at a time
+// while iterating, so query files of any size can be processed.
+pub struct StreamingInstance<Q> {
+    pub numbers: Vec<u64>,
+    pub queries: QueryStream<Q>,
+}
+
+type ParseQuery<Q> = fn(&Path, usize, &str, usize) -> Result<Q, InstanceError>;
+type ReadQuery<Q> = fn(&mut binary::BinaryReader<Reader>, usize) -> Result<Q, InstanceError>;
+
+enum QuerySource {
+    Text {
+        lines: io::Lines<Reader>,
+        // Line number of the last line read.
+        line_number: usize,
+    },
+    Binary {
+        reader: binary::BinaryReader<Reader>,
+        remaining: u64,
+    },
+}
+
+pub struct QueryStream<Q> {
+    path: PathBuf,
+    source: QuerySource,
+    numbers_count: usize,
+    parse: ParseQuery<Q>,
+    read: ReadQuery<Q>,
+}
+
+impl<Q> Iterator for QueryStream<Q> {
+    type Item = Result<Q, InstanceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            QuerySource::Text { lines, line_number } => {
+                for line in lines.by_ref() {
+                    *line_number += 1;
+
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(error) => {
+                            return Some(Err(InstanceError::Io {
+                                path: self.path.clone(),
+                                error,
+                            }))
+                        }
+                    };
+
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    return Some((self.parse)(
+                        &self.path,
+                        *line_number,
+                        line.trim(),
+                        self.numbers_count,
+                    ));
+                }
+
+                None
+            }
+            QuerySource::Binary { reader, remaining } => {
+                if *remaining == 0 {
+                    return None;
+                }
+                *remaining -= 1;
+
+                Some((self.read)(reader, self.numbers_count))
+            }
+        }
+    }
+}
+
+// Opens a text or binary instance, whichever the file starts with.
+fn stream_instance<Q>(
+    path: &Path,
+    kind: u64,
+    parse: ParseQuery<Q>,
+    read: ReadQuery<Q>,
+) -> Result<StreamingInstance<Q>, InstanceError> {
+    let mut reader = open(path)?;
+
+    let is_binary = binary::is_binary(&mut reader).map_err(|error| InstanceError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    let (numbers, source) = if is_binary {
+        let (reader, numbers, remaining) = binary::read_header(path, reader, kind)?;
+
+        (numbers, QuerySource::Binary { reader, remaining })
+    } else {
+        let (numbers, line_number) = read_numbers(path, &mut reader)?;
+
+        (
+            numbers,
+            QuerySource::Text {
+                lines: reader.lines(),
+                line_number,
+            },
+        )
+    };
+
+    Ok(StreamingInstance {
+        queries: QueryStream {
+            path: path.to_path_buf(),
+            source,
+            numbers_count: numbers.len(),
+            parse,
+            read,
+        },
+        numbers,
+    })
+}
+
+pub fn stream_pd_instance(path: &Path) -> Result<StreamingInstance<u64>, InstanceError> {
+    stream_instance(path, binary::KIND_PD, parse_pd_query, binary::read_pd_query)
+}
+
+pub fn stream_rmq_instance(
+    path: &Path,
+) -> Result<StreamingInstance<(usize, usize)>, InstanceError> {
+    stream_instance(
+        path,
+        binary::KIND_RMQ,
+        parse_rmq_query,
+        binary::read_rmq_query,
+    )
+}
+
+// The numbers are the parents of the nodes.
+pub fn stream_lca_instance(
+    path: &Path,
+) -> Result<StreamingInstance<(usize, usize)>, InstanceError> {
+    stream_instance(
+        path,
+        binary::KIND_LCA,
+        parse_lca_query,
+        binary::read_lca_query,
+    )
+}
+
+// Tree given by its undirected edges, with the queries still to be read.
+pub struct StreamingEdgesInstance {
+    pub nodes_count: usize,
+    pub edges: Vec<(usize, usize)>,
+    pub queries: QueryStream<(usize, usize)>,
+}
+
+// Opens an LCA instance that lists edges instead of parents, which only
+// exists in the text format:
+//
+//   number of nodes n
+//   n - 1 edges u,v, one per line
+//   one query u,v per line
+pub fn stream_lca_edges_instance(path: &Path) -> Result<StreamingEdgesInstance, InstanceError> {
+    let io_error = |error| InstanceError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut lines = open(path)?.lines();
+
+    let first = lines
+        .next()
+        .transpose()
+        .map_err(io_error)?
+        .unwrap_or_default();
+    let nodes_count: usize = first
+        .trim()
+        .parse()
+        .map_err(|_| InstanceError::InvalidCount {
+            path: path.to_path_buf(),
+            line: 1,
+            text: first.trim().to_string(),
+        })?;
+
+    let edges_count = nodes_count.saturating_sub(1);
+    let mut edges = Vec::new();
+    let mut line_number = 1;
+
+    while edges.len() < edges_count {
+        let text = lines.next().transpose().map_err(io_error)?.ok_or_else(|| {
+            InstanceError::MissingNumbers {
+                path: path.to_path_buf(),
+                expected: edges_count as u64,
+                found: edges.len() as u64,
+            }
+        })?;
+        line_number += 1;
+
+        edges.push(parse_lca_query(
+            path,
+            line_number,
+            text.trim(),
+            nodes_count,
+        )?);
+    }
+
+    Ok(StreamingEdgesInstance {
+        nodes_count,
+        edges,
+        queries: QueryStream {
+            path: path.to_path_buf(),
+            source: QuerySource::Text { lines, line_number },
+            numbers_count: nodes_count,
+            parse: parse_lca_query,
+            read: binary::read_lca_query,
+        },
+    })
+}
+
+#[cfg(test)]
+pub fn read_pd_instance(path: &Path) -> Result<PDInstance, InstanceError> {
+    let stream = stream_pd_instance(path)?;
+
+    Ok(PDInstance {
+        queries: stream.queries.collect::<Result<_, _>>()?,
+        numbers: stream.numbers,
+    })
+}
+
+#[cfg(test)]
+pub fn read_rmq_instance(path: &Path) -> Result<RMQInstance, InstanceError> {
+    let stream = stream_rmq_instance(path)?;
+
+    Ok(RMQInstance {
+        queries: stream.queries.collect::<Result<_, _>>()?,
+        numbers: stream.numbers,
+    })
+}
+
+fn write_text(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), InstanceError> {
+    let io_error = |error| InstanceError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut writer = io::BufWriter::new(File::create(path).map_err(io_error)?);
+    write(&mut writer).map_err(io_error)?;
+    writer.flush().map_err(io_error)
+}
+
+// Writes a PD instance in the text format.
+pub fn write_pd_instance(path: &Path, instance: &PDInstance) -> Result<(), InstanceError> {
+    write_text(path, |writer| {
+        writeln!(writer, "{}", instance.numbers.len())?;
+        for number in instance.numbers.iter().chain(&instance.queries) {
+            writeln!(writer, "{}", number)?;
+        }
+        Ok(())
+    })
+}
+
+// Writes an RMQ instance in the text format.
+pub fn write_rmq_instance(path: &Path, instance: &RMQInstance) -> Result<(), InstanceError> {
+    write_text(path, |writer| {
+        writeln!(writer, "{}", instance.numbers.len())?;
+        for number in &instance.numbers {
+            writeln!(writer, "{}", number)?;
+        }
+        for (from, to) in &instance.queries {
+            writeln!(writer, "{},{}", from, to)?;
+        }
+        Ok(())
+    })
+}
+
+// Writes an LCA instance in the text format.
+pub fn write_lca_instance(path: &Path, instance: &LCAInstance) -> Result<(), InstanceError> {
+    write_text(path, |writer| {
+        writeln!(writer, "{}", instance.parents.len())?;
+        for parent in &instance.parents {
+            writeln!(writer, "{}", parent)?;
+        }
+        for (u, v) in &instance.queries {
+            writeln!(writer, "{},{}", u, v)?;
+        }
+        Ok(())
+    })
+}
+
+// Reads a matrix instance, which only exists in the text format:
+//
+//   rows,cols
+//   one line of cols comma-separated numbers per row
+//   one query row_from,col_from,row_to,col_to per line
+//
+// Queries are not streamed, the matrix needs all of its lines anyway.
+pub fn read_matrix_instance(path: &Path) -> Result<MatrixInstance, InstanceError> {
+    let io_error = |error| InstanceError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let mut lines = open(path)?.lines();
+
+    let first = lines
+        .next()
+        .transpose()
+        .map_err(io_error)?
+        .unwrap_or_default();
+    // rows * cols has to fit, as the matrix is indexed row by row.
+    let (rows, cols, size) = first
+        .trim()
+        .split_once(',')
+        .and_then(|(a, b)| Some((a.trim().parse().ok()?, b.trim().parse().ok()?)))
+        .and_then(|(rows, cols): (usize, usize)| Some((rows, cols, rows.checked_mul(cols)?)))
+        .ok_or_else(|| InstanceError::InvalidDimensions {
+            path: path.to_path_buf(),
+            text: first.trim().to_string(),
+        })?;
+
+    debug!("Matrix of {} rows, {} cols", rows, cols);
+
+    // Not preallocated, the header alone must not allocate arbitrary memory.
+    let mut numbers: Vec<u64> = Vec::new();
+    let mut line = 1;
+
+    for row in 0..rows {
+        let text = match lines.next().transpose().map_err(io_error)? {
+            Some(text) => text,
+            None => {
+                return Err(InstanceError::MissingNumbers {
+                    path: path.to_path_buf(),
+                    expected: size as u64,
+                    found: (row * cols) as u64,
+                })
+            }
+        };
+        line += 1;
+
+        let invalid_row = || InstanceError::InvalidRow {
+            path: path.to_path_buf(),
+            line,
+            text: text.trim().to_string(),
+            cols,
+        };
+
+        let before = numbers.len();
+        for number in text.trim().split(',').filter(|_| cols > 0) {
+            numbers.push(number.trim().parse().map_err(|_| invalid_row())?);
+        }
+        if numbers.len() - before != cols {
+            return Err(invalid_row());
+        }
+    }
+
+    let mut queries = Vec::new();
+
+    for text in lines {
+        let text = text.map_err(io_error)?;
+        line += 1;
+
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        queries.push(parse_matrix_query(path, line, text.trim(), rows, cols)?);
+    }
+
+    Ok(MatrixInstance {
+        rows,
+        cols,
+        numbers,
+        queries,
+    })
+}
+
+// Writes a matrix instance in the text format.
+#[allow(dead_code)]
+pub fn write_matrix_instance(path: &Path, instance: &MatrixInstance) -> Result<(), InstanceError> {
+    write_text(path, |writer| {
+        writeln!(writer, "{},{}", instance.rows, instance.cols)?;
+        for row in instance.numbers.chunks(instance.cols.max(1)) {
+            let row: Vec<String> = row.iter().map(|number| number.to_string()).collect();
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        for (row_from, col_from, row_to, col_to) in &instance.queries {
+            writeln!(writer, "{},{},{},{}", row_from, col_from, row_to, col_to)?;
+        }
+        Ok(())
+    })
+}
+
+// Converts a PD instance, text or binary, to the binary format.
+//
+// Returns the number of queries written.
+pub fn convert_pd_to_binary(input: &Path, output: &Path) -> Result<u64, InstanceError> {
+    let instance = stream_pd_instance(input)?;
+
+    let mut writer = binary::BinaryWriter::create(output, binary::KIND_PD, &instance.numbers)?;
+    for query in instance.queries {
+        writer.write_query(&[query?])?;
+    }
+
+    writer.finish()
+}
+
+// Converts an RMQ instance, text or binary, to the binary format.
+//
+// Returns the number of queries written.
+pub fn convert_rmq_to_binary(input: &Path, output: &Path) -> Result<u64, InstanceError> {
+    let instance = stream_rmq_instance(input)?;
+
+    let mut writer = binary::BinaryWriter::create(output, binary::KIND_RMQ, &instance.numbers)?;
+    for query in instance.queries {
+        let (from, to) = query?;
+        writer.write_query(&[from as u64, to as u64])?;
+    }
+
+    writer.finish()
+}
+
+fn parse_pd_query(
+    path: &Path,
+    line: usize,
+    text: &str,
+    _numbers_count: usize,
+) -> Result<u64, InstanceError> {
+    text.parse::<u64>()
+        .map_err(|_| InstanceError::InvalidNumber {
+            path: path.to_path_buf(),
+            line,
+            text: text.to_string(),
+        })
+}
+
+// Parses `from,to` and checks it is a range inside the numbers.
+fn parse_rmq_query(
+    path: &Path,
+    line: usize,
+    text: &str,
+    numbers_count: usize,
+) -> Result<(usize, usize), InstanceError> {
+    let query = text
+        .split_once(',')
+        .and_then(|(a, b)| Some((a.trim().parse().ok()?, b.trim().parse().ok()?)))
+        .ok_or_else(|| InstanceError::InvalidQuery {
+            path: path.to_path_buf(),
+            line,
+            text: text.to_string(),
+        })?;
+
+    if query.0 > query.1 || query.1 >= numbers_count {
+        return Err(InstanceError::QueryOutOfRange {
+            path: path.to_path_buf(),
+            line,
+            text: text.to_string(),
+            numbers_count,
+        });
+    }
+
+    Ok(query)
+}
+
+// Parses `u,v` and checks both are nodes. Unlike RMQ queries, u may be
+// larger than v.
+fn parse_lca_query(
+    path: &Path,
+    line: usize,
+    text: &str,
+    nodes_count: usize,
+) -> Result<(usize, usize), InstanceError> {
+    text.split_once(',')
+        .and_then(|(a, b)| Some((a.trim().parse().ok()?, b.trim().parse().ok()?)))
+        .filter(|(u, v)| *u < nodes_count && *v < nodes_count)
+        .ok_or_else(|| InstanceError::InvalidNodes {
+            path: path.to_path_buf(),
+            line,
+            text: text.to_string(),
+            nodes_count,
+        })
+}
+
+// Parses `row_from,col_from,row_to,col_to` and checks it is a rectangle
+// inside the matrix.
+fn parse_matrix_query(
+    path: &Path,
+    line: usize,
+    text: &str,
+    rows: usize,
+    cols: usize,
+) -> Result<(usize, usize, usize, usize), InstanceError> {
+    let values: Vec<usize> = text
+        .split(',')
+        .map(|value| value.trim().parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_default();
+
+    match values[..] {
+        [row_from, col_from, row_to, col_to]
+            if row_from <= row_to && col_from <= col_to && row_to < rows && col_to < cols =>
+        {
+            Ok((row_from, col_from, row_to, col_to))
+        }
+        _ => Err(InstanceError::InvalidRectangle {
+            path: path.to_path_buf(),
+            line,
+            text: text.to_string(),
+            rows,
+            cols,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn write_instance(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("rmq_project_{}_{}.txt", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn testing_read_rmq_instance_errors() {
+        let path = write_instance("rmq_ok", "3\n5\n1\n4\n0,2\n1, 1\n");
+        let instance = read_rmq_instance(&path).unwrap();
+        assert_eq!(vec![5, 1, 4], instance.numbers);
+        assert_eq!(vec![(0, 2), (1, 1)], instance.queries);
+
+        let path = write_instance("rmq_query", "3\n5\n1\n4\n0,2\n1;2\n");
+        match read_rmq_instance(&path) {
+            Err(InstanceError::InvalidQuery { line, text, .. }) => {
+                assert_eq!(6, line);
+                assert_eq!("1;2", text);
+            }
+            _ => panic!("expected InvalidQuery"),
+        }
+
+        let path = write_instance("rmq_range", "3\n5\n1\n4\n2,3\n");
+        assert!(matches!(
+            read_rmq_instance(&path),
+            Err(InstanceError::QueryOutOfRange { line: 5, .. })
+        ));
+
+        let path = write_instance("rmq_missing", "3\n5\n1\n");
+        assert!(matches!(
+            read_rmq_instance(&path),
+            Err(InstanceError::MissingNumbers {
+                expected: 3,
+                found: 2,
+                ..
+            })
+        ));
+
+        // A count far beyond memory is an error, not an allocation failure.
+        for count in [u64::MAX, 100_000_000_000] {
+            let path = write_instance("rmq_huge", &format!("{}\n5\n", count));
+            assert!(matches!(
+                stream_rmq_instance(&path),
+                Err(InstanceError::MissingNumbers { found: 1, .. })
+            ));
+            assert!(matches!(
+                stream_pd_instance(&path),
+                Err(InstanceError::MissingNumbers { found: 1, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn testing_read_matrix_instance() {
+        let path = write_instance("matrix_ok", "2,3\n5,1,4\n2, 7,3\n0,0,1,2\n\n1,1,1,1\n");
+        let instance = read_matrix_instance(&path).unwrap();
+        assert_eq!((2, 3), (instance.rows, instance.cols));
+        assert_eq!(vec![5, 1, 4, 2, 7, 3], instance.numbers);
+        assert_eq!(vec![(0, 0, 1, 2), (1, 1, 1, 1)], instance.queries);
+
+        let copy = write_instance("matrix_copy", "");
+        write_matrix_instance(&copy, &instance).unwrap();
+        assert_eq!(
+            "2,3\n5,1,4\n2,7,3\n0,0,1,2\n1,1,1,1\n",
+            fs::read_to_string(&copy).unwrap()
+        );
+
+        let path = write_instance("matrix_dimensions", "2x3\n");
+        assert!(matches!(
+            read_matrix_instance(&path),
+            Err(InstanceError::InvalidDimensions { .. })
+        ));
+
+        let huge = format!("{},{}\n", usize::MAX, 2);
+        let path = write_instance("matrix_huge", &huge);
+        assert!(matches!(
+            read_matrix_instance(&path),
+            Err(InstanceError::InvalidDimensions { .. })
+        ));
+
+        // Fits, but the rows are missing.
+        let path = write_instance("matrix_large", "100000000,100000000\n1\n");
+        assert!(matches!(
+            read_matrix_instance(&path),
+            Err(InstanceError::InvalidRow { line: 2, .. })
+        ));
+
+        let path = write_instance("matrix_row", "2,3\n5,1,4\n2,7\n");
+        match read_matrix_instance(&path) {
+            Err(err @ InstanceError::InvalidRow { line: 3, .. }) => {
+                assert!(err
+                    .to_string()
+                    .ends_with(":3: expected row of 3 comma-separated numbers, got \"2,7\""));
+            }
+            _ => panic!("expected InvalidRow"),
+        }
+
+        let path = write_instance("matrix_missing", "2,3\n5,1,4\n");
+        assert!(matches!(
+            read_matrix_instance(&path),
+            Err(InstanceError::MissingNumbers {
+                expected: 6,
+                found: 3,
+                ..
+            })
+        ));
+
+        for query in ["0,0,2,0", "1,0,0,0", "0,0,1", "0,0,1,x"] {
+            let path = write_instance("matrix_query", &format!("2,3\n5,1,4\n2,7,3\n{}\n", query));
+            assert!(matches!(
+                read_matrix_instance(&path),
+                Err(InstanceError::InvalidRectangle { line: 4, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn testing_read_pd_instance_errors() {
+        let path = write_instance("pd_count", "-1\n");
+        assert!(matches!(
+            read_pd_instance(&path),
+            Err(InstanceError::InvalidCount { line: 1, .. })
+        ));
+
+        let path = write_instance("pd_number", "2\n1\nx\n");
+        match read_pd_instance(&path) {
+            Err(err @ InstanceError::InvalidNumber { line: 3, .. }) => {
+                assert!(err
+                    .to_string()
+                    .ends_with(":3: expected unsigned number, got \"x\""));
+            }
+            _ => panic!("expected InvalidNumber"),
+        }
+
+        let path = Path::new("testdata/does_not_exist.txt");
+        assert!(matches!(
+            read_pd_instance(path),
+            Err(InstanceError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn testing_stream_rmq_instance() {
+        let path = write_instance("rmq_stream", "3\n5\n1\n4\n0,2\n\n1,1\n2,1\n0,0\n");
+        let mut instance = stream_rmq_instance(&path).unwrap();
+        assert_eq!(vec![5, 1, 4], instance.numbers);
+
+        // Queries before the broken one are handed out, the error reports
+        // the right line even after skipping an empty line.
+        assert_eq!(Some((0, 2)), instance.queries.next().map(|q| q.unwrap()));
+        assert_eq!(Some((1, 1)), instance.queries.next().map(|q| q.unwrap()));
+        assert!(matches!(
+            instance.queries.next(),
+            Some(Err(InstanceError::QueryOutOfRange { line: 8, .. }))
+        ));
+        assert_eq!(Some((0, 0)), instance.queries.next().map(|q| q.unwrap()));
+        assert!(instance.queries.next().is_none());
+
+        let path = write_instance("pd_stream", "2\n1\n3\n0\n4\n");
+        let instance = stream_pd_instance(&path).unwrap();
+        assert_eq!(vec![1, 3], instance.numbers);
+        assert_eq!(
+            vec![0, 4],
+            instance.queries.collect::<Result<Vec<_>, _>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_binary_roundtrip() {
+        let text = write_instance("rmq_text", "3\n5\n1\n4\n0,2\n1,1\n");
+        let bin = std::env::temp_dir().join(format!("rmq_project_rmq_bin_{}", std::process::id()));
+
+        assert_eq!(2, convert_rmq_to_binary(&text, &bin).unwrap());
+        let instance = read_rmq_instance(&bin).unwrap();
+        assert_eq!(vec![5, 1, 4], instance.numbers);
+        assert_eq!(vec![(0, 2), (1, 1)], instance.queries);
+
+        // Magic, kind and count, 3 numbers, query count, 2 queries.
+        let bytes = fs::read(&bin).unwrap();
+        assert_eq!(8 * 3 + 8 * 3 + 8 + 8 * 4, bytes.len());
+
+        // A binary RMQ instance is no PD instance.
+        assert!(matches!(
+            read_pd_instance(&bin),
+            Err(InstanceError::InvalidBinary { offset: 8, .. })
+        ));
+
+        // Cut off in the middle of the last query.
+        fs::write(&bin, &bytes[..bytes.len() - 4]).unwrap();
+        match read_rmq_instance(&bin) {
+            Err(err @ InstanceError::InvalidBinary { .. }) => {
+                assert!(err.to_string().ends_with("file ends inside the queries"));
+            }
+            _ => panic!("expected InvalidBinary"),
+        }
+
+        let text = write_instance("pd_text", "2\n1\n3\n0\n4\n");
+        assert_eq!(2, convert_pd_to_binary(&text, &bin).unwrap());
+        let instance = read_pd_instance(&bin).unwrap();
+        assert_eq!(vec![1, 3], instance.numbers);
+        assert_eq!(vec![0, 4], instance.queries);
+    }
+
+    fn write_compressed(name: &str, content: &str) -> (PathBuf, PathBuf) {
+        let gz =
+            std::env::temp_dir().join(format!("rmq_project_{}_{}.gz", name, std::process::id()));
+        let mut encoder = flate2::write::GzEncoder::new(
+            fs::File::create(&gz).unwrap(),
+            flate2::Compression::default(),
+        );
+        io::Write::write_all(&mut encoder, content.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let zst =
+            std::env::temp_dir().join(format!("rmq_project_{}_{}.zst", name, std::process::id()));
+        fs::write(&zst, zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+
+        (gz, zst)
+    }
+
+    #[test]
+    fn testing_compressed_instances() {
+        let (gz, zst) = write_compressed("rmq_compressed", "3\n5\n1\n4\n0,2\n1,1\n");
+
+        for path in [&gz, &zst] {
+            let instance = read_rmq_instance(path).unwrap();
+            assert_eq!(vec![5, 1, 4], instance.numbers);
+            assert_eq!(vec![(0, 2), (1, 1)], instance.queries);
+        }
+
+        // Binary instances can be compressed as well.
+        let bin = std::env::temp_dir().join(format!(
+            "rmq_project_pd_compressed_bin_{}",
+            std::process::id()
+        ));
+        let text = write_instance("pd_compressed_text", "2\n1\n3\n0\n4\n");
+        convert_pd_to_binary(&text, &bin).unwrap();
+        let zst = bin.with_extension("zst");
+        fs::write(
+            &zst,
+            zstd::encode_all(&fs::read(&bin).unwrap()[..], 0).unwrap(),
+        )
+        .unwrap();
+        let instance = read_pd_instance(&zst).unwrap();
+        assert_eq!(vec![1, 3], instance.numbers);
+        assert_eq!(vec![0, 4], instance.queries);
+
+        // Not actually compressed, but named like it.
+        let fake = write_instance("fake", "1\n1\n");
+        let fake_gz = fake.with_extension("gz");
+        fs::rename(&fake, &fake_gz).unwrap();
+        assert!(matches!(
+            read_pd_instance(&fake_gz),
+            Err(InstanceError::Io { .. })
+        ));
+    }
+}
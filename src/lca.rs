@@ -0,0 +1,586 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+
+use super::heapsize;
+use super::instances;
+use super::report;
+use super::rmq::{Algorithm, RMQError, RMQ};
+
+use crate::instances::{InstanceError, QueryStream};
+use crate::malloc_size_of::MallocSizeOf;
+use crate::malloc_size_of::MallocSizeOfOps;
+use log::{debug, info, trace};
+
+#[derive(Debug, PartialEq)]
+pub enum TreeError {
+    Empty,
+    ParentOutOfRange { node: usize, parent: usize },
+    NoRoot,
+    RootOutOfRange { root: usize },
+    MultipleRoots { first: usize, second: usize },
+    // Not reachable from the root, so part of a cycle or hanging below one.
+    Unreachable { node: usize },
+    EdgeOutOfRange { u: usize, v: usize },
+    // A tree on n nodes has n - 1 edges.
+    EdgeCount { nodes: usize, edges: usize },
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreeError::Empty => f.write_str("tree has no nodes"),
+            TreeError::ParentOutOfRange { node, parent } => {
+                write!(f, "parent {} of node {} is not a node", parent, node)
+            }
+            TreeError::NoRoot => f.write_str("no node is its own parent"),
+            TreeError::RootOutOfRange { root } => write!(f, "root {} is not a node", root),
+            TreeError::MultipleRoots { first, second } => {
+                write!(f, "nodes {} and {} are both roots", first, second)
+            }
+            TreeError::Unreachable { node } => {
+                write!(f, "node {} is not reachable from the root", node)
+            }
+            TreeError::EdgeOutOfRange { u, v } => {
+                write!(f, "edge {},{} has an end that is not a node", u, v)
+            }
+            TreeError::EdgeCount { nodes, edges } => {
+                write!(f, "{} nodes need {} edges, got {}", nodes, nodes - 1, edges)
+            }
+        }
+    }
+}
+
+impl Error for TreeError {}
+
+// A rooted tree on the nodes 0..n.
+pub struct Tree {
+    root: usize,
+    // The root is its own parent.
+    parents: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    depths: Vec<usize>,
+}
+
+impl Tree {
+    // Exactly one node, the root, has to be its own parent.
+    pub fn from_parents(parents: &[usize]) -> Result<Self, TreeError> {
+        let n = parents.len();
+        if n == 0 {
+            return Err(TreeError::Empty);
+        }
+
+        let mut root = None;
+        let mut children = vec![Vec::new(); n];
+
+        for (node, parent) in parents.iter().enumerate() {
+            if *parent >= n {
+                return Err(TreeError::ParentOutOfRange {
+                    node,
+                    parent: *parent,
+                });
+            }
+
+            if *parent == node {
+                if let Some(first) = root {
+                    return Err(TreeError::MultipleRoots {
+                        first,
+                        second: node,
+                    });
+                }
+                root = Some(node);
+            } else {
+                children[*parent].push(node);
+            }
+        }
+
+        let root = root.ok_or(TreeError::NoRoot)?;
+
+        Self::with_children(root, parents.to_vec(), children)
+    }
+
+    // Undirected edges, oriented away from root.
+    pub fn from_edges(n: usize, root: usize, edges: &[(usize, usize)]) -> Result<Self, TreeError> {
+        if n == 0 {
+            return Err(TreeError::Empty);
+        }
+        if root >= n {
+            return Err(TreeError::RootOutOfRange { root });
+        }
+        if edges.len() != n - 1 {
+            return Err(TreeError::EdgeCount {
+                nodes: n,
+                edges: edges.len(),
+            });
+        }
+
+        let mut neighbours = vec![Vec::new(); n];
+        for (u, v) in edges {
+            if *u >= n || *v >= n {
+                return Err(TreeError::EdgeOutOfRange { u: *u, v: *v });
+            }
+
+            neighbours[*u].push(*v);
+            neighbours[*v].push(*u);
+        }
+
+        // Orient the edges by a traversal from the root. With n - 1 edges,
+        // everything is reached exactly if there is no cycle.
+        let mut parents = vec![usize::MAX; n];
+        let mut children = vec![Vec::new(); n];
+        parents[root] = root;
+
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            for neighbour in &neighbours[node] {
+                if parents[*neighbour] == usize::MAX {
+                    parents[*neighbour] = node;
+                    children[node].push(*neighbour);
+                    stack.push(*neighbour);
+                }
+            }
+        }
+
+        Self::with_children(root, parents, children)
+    }
+
+    // Computes the depths and checks that every node hangs below the root.
+    fn with_children(
+        root: usize,
+        parents: Vec<usize>,
+        children: Vec<Vec<usize>>,
+    ) -> Result<Self, TreeError> {
+        let n = parents.len();
+
+        let mut depths = vec![usize::MAX; n];
+        depths[root] = 0;
+
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            for child in &children[node] {
+                depths[*child] = depths[node] + 1;
+                stack.push(*child);
+            }
+        }
+
+        if let Some(node) = depths.iter().position(|depth| *depth == usize::MAX) {
+            return Err(TreeError::Unreachable { node });
+        }
+
+        Ok(Self {
+            root,
+            parents,
+            children,
+            depths,
+        })
+    }
+
+    pub fn nodes_count(&self) -> usize {
+        self.parents.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    #[allow(dead_code)]
+    pub fn parent(&self, node: usize) -> usize {
+        self.parents[node]
+    }
+
+    #[allow(dead_code)]
+    pub fn depth(&self, node: usize) -> usize {
+        self.depths[node]
+    }
+}
+
+// Lowest common ancestors via the Euler tour: the LCA of u and v is the
+// shallowest node visited between the first visits of u and v, found by
+// an RMQ over the depths along the tour.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(MallocSizeOf)]
+pub struct LCA {
+    // Node at every step of the tour, 2n - 1 steps.
+    euler: Vec<usize>,
+    // First step of every node.
+    first: Vec<usize>,
+    // Over the depth at every step.
+    rmq: Box<dyn RMQ>,
+}
+
+impl LCA {
+    pub fn new(tree: &Tree, algorithm: Algorithm) -> Self {
+        let n = tree.nodes_count();
+
+        let mut euler = Vec::with_capacity(2 * n - 1);
+        let mut depths: Vec<u64> = Vec::with_capacity(2 * n - 1);
+        let mut first = vec![0usize; n];
+
+        // Iterative, a path of a million nodes must not overflow the stack.
+        // Every entry is a node and the number of its children visited.
+        let mut stack = vec![(tree.root, 0usize)];
+        euler.push(tree.root);
+        depths.push(0);
+
+        while let Some((node, visited)) = stack.last_mut() {
+            let node = *node;
+
+            match tree.children[node].get(*visited) {
+                Some(child) => {
+                    *visited += 1;
+
+                    first[*child] = euler.len();
+                    euler.push(*child);
+                    depths.push(tree.depths[*child] as u64);
+                    stack.push((*child, 0));
+                }
+                None => {
+                    stack.pop();
+
+                    // Back up in the parent.
+                    if let Some((parent, _)) = stack.last() {
+                        euler.push(*parent);
+                        depths.push(tree.depths[*parent] as u64);
+                    }
+                }
+            }
+        }
+
+        debug!("Euler tour of {} nodes has {} steps", n, euler.len());
+
+        Self {
+            euler,
+            first,
+            rmq: algorithm.build(depths),
+        }
+    }
+
+    pub fn lca(&self, u: usize, v: usize) -> Result<usize, RMQError> {
+        if u >= self.first.len() || v >= self.first.len() {
+            return Err(RMQError::OutOfRange);
+        }
+
+        let a = self.first[u];
+        let b = self.first[v];
+
+        let step = self.rmq.range_minimum_query(a.min(b), a.max(b))?;
+
+        trace!("lca({}, {}): steps {}..={} -> {}", u, v, a, b, step);
+
+        Ok(self.euler[step])
+    }
+}
+
+// Walks up from the deeper node until both meet. Oracle for LCA.
+pub fn lca_naive(tree: &Tree, u: usize, v: usize) -> usize {
+    let (mut u, mut v) = (u, v);
+
+    while u != v {
+        if tree.depths[u] >= tree.depths[v] {
+            u = tree.parents[u];
+        } else {
+            v = tree.parents[v];
+        }
+    }
+
+    u
+}
+
+// Tree of an instance as it is written in the file.
+enum TreeInput {
+    Parents(Vec<u64>),
+    // Rooted at node 0.
+    Edges {
+        nodes_count: usize,
+        edges: Vec<(usize, usize)>,
+    },
+}
+
+// Reads the tree of an instance, given by parents or with edges by its edge
+// list, and leaves the queries to be streamed.
+fn stream_instance(
+    path: &Path,
+    edges: bool,
+) -> Result<(TreeInput, QueryStream<(usize, usize)>), InstanceError> {
+    if edges {
+        let instance = instances::stream_lca_edges_instance(path)?;
+
+        return Ok((
+            TreeInput::Edges {
+                nodes_count: instance.nodes_count,
+                edges: instance.edges,
+            },
+            instance.queries,
+        ));
+    }
+
+    let instance = instances::stream_lca_instance(path)?;
+
+    Ok((TreeInput::Parents(instance.numbers), instance.queries))
+}
+
+fn read_tree(path: &Path, input: &TreeInput) -> Result<Tree, InstanceError> {
+    let tree = match input {
+        TreeInput::Parents(parents) => {
+            let parents: Vec<usize> = parents.iter().map(|parent| *parent as usize).collect();
+
+            Tree::from_parents(&parents)
+        }
+        TreeInput::Edges { nodes_count, edges } => Tree::from_edges(*nodes_count, 0, edges),
+    };
+
+    tree.map_err(|error| InstanceError::InvalidTree {
+        path: path.to_path_buf(),
+        reason: error.to_string(),
+    })
+}
+
+// Answers the queries of an LCA instance, the RMQ over the Euler tour is
+// built with algorithm. With edges, the instance lists the edges of the tree
+// instead of the parents.
+pub fn benchmark_streaming(
+    path: &Path,
+    edges: bool,
+    algorithm: Algorithm,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    info!("lca");
+
+    let (input, queries) = stream_instance(path, edges)?;
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    let tree = read_tree(path, &input)?;
+    let lca = LCA::new(&tree, algorithm);
+
+    let mut measurement = report::Measurement::new("lca", start.elapsed());
+
+    for (i, query) in queries.enumerate() {
+        let (u, v) = query?;
+
+        let start = Instant::now();
+        let got = lca.lca(u, v).unwrap();
+        measurement.record_query(start.elapsed());
+
+        writer.push(got as u64);
+
+        if i % 100 == 0 {
+            debug!("Query nr {}", i);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = lca.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
+}
+
+// Answers every query with LCA and lca_naive. Returns the number of
+// mismatches, the first few are printed.
+pub fn check_against_naive(
+    path: &Path,
+    edges: bool,
+    algorithm: Algorithm,
+) -> Result<u64, InstanceError> {
+    let (input, queries) = stream_instance(path, edges)?;
+
+    let tree = read_tree(path, &input)?;
+    let lca = LCA::new(&tree, algorithm);
+
+    let mut mismatches = 0;
+
+    for (i, query) in queries.enumerate() {
+        let (u, v) = query?;
+
+        let want = lca_naive(&tree, u, v);
+        let got = lca.lca(u, v);
+
+        if got != Ok(want) {
+            if mismatches < 10 {
+                eprintln!(
+                    "Mismatch in query nr {}: {},{} want: {} got: {:?}",
+                    i, u, v, want, got
+                );
+            }
+            mismatches += 1;
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::ValueEnum;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::generator;
+
+    #[test]
+    fn testing_lca_all_algorithms() {
+        let mut rng = StdRng::seed_from_u64(4);
+
+        for n in [1, 2, 3, 10, 100, 300] {
+            // Random recursive trees and a path.
+            let random: Vec<usize> = (0..n)
+                .map(|i| if i == 0 { 0 } else { rng.gen_range(0..i) })
+                .collect();
+            let path: Vec<usize> = (0..n).map(|i| i.saturating_sub(1)).collect();
+
+            for parents in [random, path] {
+                let tree = Tree::from_parents(&parents).unwrap();
+
+                for algorithm in Algorithm::value_variants() {
+                    let lca = LCA::new(&tree, *algorithm);
+
+                    for u in 0..n {
+                        for v in 0..n {
+                            assert_eq!(
+                                Ok(lca_naive(&tree, u, v)),
+                                lca.lca(u, v),
+                                "{:?} n: {} lca({}, {})",
+                                algorithm,
+                                n,
+                                u,
+                                v
+                            );
+                        }
+                    }
+
+                    assert_eq!(Err(RMQError::OutOfRange), lca.lca(0, n));
+                }
+            }
+        }
+
+        //     2
+        //    / \
+        //   0   3
+        //  / \
+        // 1   4
+        let tree = Tree::from_edges(5, 2, &[(0, 1), (2, 0), (3, 2), (4, 0)]).unwrap();
+        assert_eq!(2, tree.root());
+        assert_eq!(0, tree.parent(4));
+        assert_eq!(2, tree.depth(1));
+
+        let lca = LCA::new(&tree, Algorithm::SpanningBlocks);
+        assert_eq!(Ok(0), lca.lca(1, 4));
+        assert_eq!(Ok(2), lca.lca(1, 3));
+        assert_eq!(Ok(0), lca.lca(0, 4));
+        assert_eq!(Ok(3), lca.lca(3, 3));
+    }
+
+    #[test]
+    fn testing_tree_errors() {
+        assert_eq!(Some(TreeError::Empty), Tree::from_parents(&[]).err());
+        assert_eq!(Some(TreeError::NoRoot), Tree::from_parents(&[1, 0]).err());
+        assert_eq!(
+            Some(TreeError::MultipleRoots {
+                first: 0,
+                second: 2
+            }),
+            Tree::from_parents(&[0, 0, 2]).err()
+        );
+        assert_eq!(
+            Some(TreeError::ParentOutOfRange { node: 1, parent: 5 }),
+            Tree::from_parents(&[0, 5]).err()
+        );
+        // 1 and 2 are each other's parent.
+        assert_eq!(
+            Some(TreeError::Unreachable { node: 1 }),
+            Tree::from_parents(&[0, 2, 1]).err()
+        );
+
+        assert_eq!(
+            Some(TreeError::RootOutOfRange { root: 3 }),
+            Tree::from_edges(3, 3, &[(0, 1), (1, 2)]).err()
+        );
+        assert_eq!(
+            Some(TreeError::EdgeCount { nodes: 3, edges: 1 }),
+            Tree::from_edges(3, 0, &[(0, 1)]).err()
+        );
+        assert_eq!(
+            Some(TreeError::EdgeOutOfRange { u: 0, v: 3 }),
+            Tree::from_edges(3, 0, &[(0, 1), (0, 3)]).err()
+        );
+        // A cycle 0-1-2 leaves 3 unreachable.
+        assert_eq!(
+            Some(TreeError::Unreachable { node: 3 }),
+            Tree::from_edges(4, 0, &[(0, 1), (1, 2), (2, 0)]).err()
+        );
+    }
+
+    #[test]
+    fn testing_lca_instance() {
+        let options = generator::GeneratorOptions {
+            size: 1000,
+            queries: 500,
+            max_value: 0,
+            values: generator::ValueDistribution::Uniform,
+            query_distribution: generator::QueryDistribution::Uniform,
+            seed: 2,
+        };
+        let instance = generator::generate_lca(&options);
+
+        let path = std::env::temp_dir().join(format!("rmq_project_lca_{}.txt", std::process::id()));
+        instances::write_lca_instance(&path, &instance).unwrap();
+
+        assert_eq!(
+            0,
+            check_against_naive(&path, false, Algorithm::SpanningBlocks).unwrap()
+        );
+        assert_eq!(
+            0,
+            check_against_naive(&path, false, Algorithm::Sparse).unwrap()
+        );
+
+        let broken = instances::LCAInstance {
+            parents: vec![1, 0],
+            queries: vec![(0, 1)],
+        };
+        instances::write_lca_instance(&path, &broken).unwrap();
+        assert!(matches!(
+            check_against_naive(&path, false, Algorithm::Sparse),
+            Err(InstanceError::InvalidTree { .. })
+        ));
+
+        std::fs::write(&path, "2\n0\n0\n0,2\n").unwrap();
+        assert!(matches!(
+            check_against_naive(&path, false, Algorithm::Sparse),
+            Err(InstanceError::InvalidNodes { line: 4, .. })
+        ));
+
+        // The tree from testing_lca_all_algorithms, rooted at 0 this time.
+        std::fs::write(&path, "5\n0,1\n2,0\n3,2\n4,0\n1,4\n3,1\n").unwrap();
+        assert_eq!(
+            0,
+            check_against_naive(&path, true, Algorithm::SpanningBlocks).unwrap()
+        );
+
+        std::fs::write(&path, "3\n0,1\n1,0\n").unwrap();
+        assert!(matches!(
+            check_against_naive(&path, true, Algorithm::Sparse),
+            Err(InstanceError::InvalidTree { .. })
+        ));
+
+        std::fs::write(&path, "3\n0,1\n").unwrap();
+        assert!(matches!(
+            check_against_naive(&path, true, Algorithm::Sparse),
+            Err(InstanceError::MissingNumbers {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+    }
+}
@@ -1,10 +1,14 @@
 mod bitvector;
 mod debug;
+mod generator;
 mod heapsize;
 mod instances;
+mod lca;
 mod predecessor;
 mod report;
 mod rmq;
+mod suffix_array;
+mod wavelet;
 
 extern crate graphannis_malloc_size_of as malloc_size_of;
 #[macro_use]
@@ -15,39 +19,743 @@ extern crate graphannis_malloc_size_of_derive as malloc_size_of_derive;
 use std::env::{self};
 use std::error::Error;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+use log::info;
+
+#[derive(Parser, Debug)]
+#[command(about = "Benchmarks predecessor, range minimum and bitvector data structures")]
+struct Cli {
+    #[arg(short, long, global = true, help = "Log arguments and progress")]
+    verbose: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Log filter like `debug` or `rmq_project::rmq=trace`, added to RMQ_LOG"
+    )]
+    log: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "RMQ_REPORT_FORMAT",
+        default_value = "text",
+        help = "How benchmark results are printed"
+    )]
+    report_format: report::ReportFormat,
+
+    #[arg(
+        long,
+        global = true,
+        env = "RMQ_REPORT_LABEL",
+        default_value_t = report::ReportOptions::default().label,
+        hide_default_value = true,
+        help = "Name of the run in the benchmark results, the algorithm name if empty"
+    )]
+    label: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args, Debug)]
+struct OutputArgs {
+    #[arg(help = "File to write the answers to")]
+    output: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "comma",
+        help = "Format of the answers"
+    )]
+    format: report::OutputFormat,
+
+    #[arg(long, default_value_t = 1, help = "Number of benchmark runs")]
+    repeat: u32,
+}
+
+impl OutputArgs {
+    // Answers are only written in the first run, the others would write the
+    // same again.
+    fn output(&self, run: u32) -> Option<report::Output> {
+        match (&self.output, run) {
+            (Some(path), 0) => Some(report::Output {
+                path: path.clone(),
+                format: self.format,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum PdOracle {
+    // Binary search over the sorted numbers.
+    Naive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum LcaOracle {
+    // Walking up from the deeper node.
+    Naive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum LceOracle {
+    // Comparing byte by byte.
+    Naive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum RangeQueryOracle {
+    // Combining the numbers one by one.
+    Naive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum Rmq2dOracle {
+    // Looking at every number of the rectangle.
+    Naive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum SmallerValuesOracle {
+    // Arrays of all answers, built with a stack in linear time.
+    Precomputed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum InstanceKind {
+    Pd,
+    Rmq,
+    Lca,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "Benchmark predecessor queries with Elias-Fano")]
+    Pd {
+        input: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(long, value_enum, help = "Check all answers before benchmarking")]
+        check_against: Option<PdOracle>,
+    },
+
+    #[command(about = "Benchmark range minimum queries")]
+    Rmq {
+        input: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(long, value_enum, default_value = "spanning-blocks")]
+        algo: rmq::Algorithm,
+
+        #[arg(long, value_enum, help = "Check all answers before benchmarking")]
+        check_against: Option<rmq::Algorithm>,
+
+        #[arg(
+            long,
+            help = "Block size of spanning-blocks [default: ceil(log2(n) / 4)]"
+        )]
+        block_size: Option<usize>,
+    },
+
+    #[command(about = "Benchmark range minimum queries on rectangles of a matrix")]
+    Rmq2d {
+        input: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "spanning-blocks",
+            help = "RMQ over the columns of every band of rows"
+        )]
+        algo: rmq::Algorithm,
+
+        #[arg(long, value_enum, help = "Check all answers before benchmarking")]
+        check_against: Option<Rmq2dOracle>,
+    },
+
+    #[command(about = "Benchmark sum, xor, gcd, max or min of ranges on an RMQ instance")]
+    RangeQuery {
+        input: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(long, value_enum, default_value = "sum")]
+        op: rmq::Operation,
+
+        #[arg(
+            long,
+            help = "Disjoint sparse table also for gcd, max and min instead of the sparse table"
+        )]
+        disjoint: bool,
+
+        #[arg(long, value_enum, help = "Check all answers before benchmarking")]
+        check_against: Option<RangeQueryOracle>,
+    },
+
+    #[command(about = "Benchmark the k smallest numbers of every range of an RMQ instance")]
+    TopK {
+        input: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(long, value_enum, default_value = "spanning-blocks")]
+        algo: rmq::Algorithm,
+
+        #[arg(long, default_value_t = 10, help = "Smallest numbers per range")]
+        k: usize,
+
+        #[arg(
+            long,
+            help = "All positions of the minimum of every range instead of the k smallest"
+        )]
+        all_minima: bool,
+    },
+
+    #[command(
+        about = "Benchmark previous and next smaller values of every number of an RMQ instance"
+    )]
+    SmallerValues {
+        input: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(long, value_enum, default_value = "spanning-blocks")]
+        algo: rmq::Algorithm,
+
+        #[arg(
+            long,
+            help = "Only look this many positions ahead for the next smaller value"
+        )]
+        within: Option<usize>,
+
+        #[arg(long, value_enum, help = "Check all answers before benchmarking")]
+        check_against: Option<SmallerValuesOracle>,
+    },
+
+    #[command(about = "Pick the block size of spanning-blocks for the numbers of an RMQ instance")]
+    TuneBlockSize {
+        input: PathBuf,
+
+        #[arg(long, value_enum, default_value = "total")]
+        objective: rmq::Objective,
+
+        #[arg(long, default_value_t = 100_000, help = "Number of random queries")]
+        queries: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Block sizes to try, like 2,4,8 [default: 1 to twice the default block size]"
+        )]
+        candidates: Vec<usize>,
+    },
+
+    #[command(about = "Benchmark lowest common ancestor queries via RMQ")]
+    Lca {
+        input: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "spanning-blocks",
+            help = "RMQ over the Euler tour"
+        )]
+        algo: rmq::Algorithm,
+
+        #[arg(long, value_enum, help = "Check all answers before benchmarking")]
+        check_against: Option<LcaOracle>,
+
+        #[arg(
+            long,
+            help = "The instance lists the n - 1 edges u,v instead of the parents, rooted at node 0"
+        )]
+        edges: bool,
+    },
+
+    #[command(about = "Benchmark longest common extension queries on the bytes of a file")]
+    Lce {
+        text: PathBuf,
+
+        #[command(flatten)]
+        output: OutputArgs,
+
+        #[arg(
+            long,
+            value_enum,
+            default_value = "spanning-blocks",
+            help = "RMQ over the LCP array"
+        )]
+        algo: rmq::Algorithm,
+
+        #[arg(long, default_value_t = 100_000, help = "Number of random queries")]
+        queries: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        #[arg(long, value_enum, help = "Check all answers before benchmarking")]
+        check_against: Option<LceOracle>,
+    },
+
+    #[command(about = "Benchmark rank1 and select1 on a random bitvector")]
+    Bitvector {
+        #[arg(long, default_value_t = 1 << 20, help = "Number of bits")]
+        len: u64,
+
+        #[arg(long, default_value_t = 0.5, help = "Probability of a bit being set")]
+        density: f64,
+
+        #[arg(
+            long,
+            default_value_t = 100_000,
+            help = "Number of queries of each kind"
+        )]
+        queries: u64,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        #[arg(long, default_value_t = 1, help = "Number of benchmark runs")]
+        repeat: u32,
+
+        #[arg(long, value_enum, help = "Check all answers")]
+        check_against: Option<bitvector::Oracle>,
+    },
+
+    #[command(about = "Generate a synthetic instance in the text format")]
+    Generate {
+        #[arg(value_enum)]
+        kind: InstanceKind,
+
+        output: PathBuf,
+
+        #[arg(long, default_value_t = 100_000, help = "Number of numbers")]
+        size: usize,
+
+        #[arg(long, default_value_t = 100_000, help = "Number of queries")]
+        queries: usize,
+
+        #[arg(long, default_value_t = u64::MAX / 2, help = "Largest generated number")]
+        max_value: u64,
+
+        #[arg(long, value_enum, default_value = "uniform")]
+        values: generator::ValueDistribution,
+
+        #[arg(long, value_enum, default_value = "uniform")]
+        query_distribution: generator::QueryDistribution,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+
+    #[command(about = "Compare RMQ algorithms to naive-slow on random arrays")]
+    Differential {
+        #[arg(long, default_value_t = 1000, help = "Number of random arrays")]
+        cases: usize,
+
+        #[arg(long, default_value_t = 200, help = "Largest array")]
+        max_size: usize,
+
+        #[arg(long, default_value_t = 100, help = "Queries on every array")]
+        queries: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Algorithm to check, can be repeated [default: all]"
+        )]
+        algo: Vec<rmq::Algorithm>,
+    },
+
+    #[command(
+        about = "Write the Cartesian tree of the numbers of an RMQ instance as Graphviz dot"
+    )]
+    CartesianTree { input: PathBuf, output: PathBuf },
+
+    #[command(about = "Convert a PD instance to the binary format")]
+    ConvertPd { input: PathBuf, output: PathBuf },
+
+    #[command(about = "Convert an RMQ instance to the binary format")]
+    ConvertRmq { input: PathBuf, output: PathBuf },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
-    dbg!(args.clone());
 
     real_main(args).await
 }
 
 async fn real_main(args: Vec<String>) -> Result<(), Box<dyn Error>> {
-    if args.len() != 4 {
-        println!("Usage: {} <command> <input-file> <out-file>", args[0]);
+    let cli = Cli::parse_from(args);
+
+    debug::init(cli.log.as_deref(), cli.verbose);
+
+    info!("{:?}", cli);
+
+    report::set_options(report::ReportOptions {
+        format: cli.report_format,
+        label: cli.label.clone(),
+    });
+
+    let result = match &cli.command {
+        Command::Pd {
+            input,
+            output,
+            check_against,
+        } => {
+            if let Some(PdOracle::Naive) = check_against {
+                info!("Checking against naive predecessor");
+                exit_on_mismatches(predecessor::check_against_naive(input), "naive");
+            }
+
+            (0..output.repeat)
+                .try_for_each(|run| predecessor::benchmark_streaming(input, output.output(run)))
+        }
+        Command::Rmq {
+            input,
+            output,
+            algo,
+            check_against,
+            block_size,
+        } => {
+            if block_size.is_some() && *algo != rmq::Algorithm::SpanningBlocks {
+                eprintln!("--block-size only applies to spanning-blocks");
+                std::process::exit(1);
+            }
+
+            if let Some(oracle) = check_against {
+                info!("Checking {:?} against {:?}", algo, oracle);
+                let build = |numbers| match block_size {
+                    Some(block_size) => Ok(rmq::spanning_blocks(numbers, *block_size)?),
+                    None => Ok(algo.build(numbers)),
+                };
+                exit_on_mismatches(
+                    exit_on_build_error(rmq::check_against(input, build, *oracle)),
+                    &format!("{:?}", oracle),
+                );
+            }
+
+            (0..output.repeat).try_for_each(|run| match block_size {
+                Some(block_size) => exit_on_build_error(rmq::benchmark_spanning_blocks(
+                    input,
+                    *block_size,
+                    output.output(run),
+                )),
+                None => algo.benchmark(input, output.output(run)),
+            })
+        }
+        Command::Rmq2d {
+            input,
+            output,
+            algo,
+            check_against,
+        } => {
+            if let Some(Rmq2dOracle::Naive) = check_against {
+                info!("Checking {:?} against naive 2D RMQ", algo);
+                exit_on_mismatches(rmq::check_2d(input, *algo), "naive");
+            }
+
+            (0..output.repeat)
+                .try_for_each(|run| rmq::benchmark_2d(input, *algo, output.output(run)))
+        }
+        Command::RangeQuery {
+            input,
+            output,
+            op,
+            disjoint,
+            check_against,
+        } => {
+            if let Some(RangeQueryOracle::Naive) = check_against {
+                info!("Checking {:?} against naive range queries", op);
+                exit_on_mismatches(rmq::check_range_query(input, *op, *disjoint), "naive");
+            }
+
+            (0..output.repeat).try_for_each(|run| {
+                rmq::benchmark_range_query(input, *op, *disjoint, output.output(run))
+            })
+        }
+        Command::TopK {
+            input,
+            output,
+            algo,
+            k,
+            all_minima,
+        } => {
+            let k = (!all_minima).then_some(*k);
+
+            (0..output.repeat)
+                .try_for_each(|run| rmq::benchmark_top_k(input, *algo, k, output.output(run)))
+        }
+        Command::SmallerValues {
+            input,
+            output,
+            algo,
+            within,
+            check_against,
+        } => {
+            if let Some(SmallerValuesOracle::Precomputed) = check_against {
+                info!("Checking {:?} against precomputed smaller values", algo);
+                exit_on_mismatches(
+                    rmq::check_smaller_values(input, *algo, *within),
+                    "precomputed",
+                );
+            }
+
+            (0..output.repeat).try_for_each(|run| {
+                rmq::benchmark_smaller_values(input, *algo, *within, output.output(run))
+            })
+        }
+        Command::TuneBlockSize {
+            input,
+            objective,
+            queries,
+            seed,
+            candidates,
+        } => instances::stream_rmq_instance(input).map(|instance| {
+            match rmq::tune_block_size(&instance.numbers, candidates, *queries, *seed, *objective) {
+                Ok((best, measured)) => {
+                    for candidate in measured {
+                        println!("{}", candidate);
+                    }
+                    println!("Best block size for {:?}: {}", objective, best);
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }),
+        Command::Lca {
+            input,
+            output,
+            algo,
+            check_against,
+            edges,
+        } => {
+            if let Some(LcaOracle::Naive) = check_against {
+                info!("Checking {:?} against naive LCA", algo);
+                exit_on_mismatches(lca::check_against_naive(input, *edges, *algo), "naive");
+            }
+
+            (0..output.repeat).try_for_each(|run| {
+                lca::benchmark_streaming(input, *edges, *algo, output.output(run))
+            })
+        }
+        Command::Lce {
+            text,
+            output,
+            algo,
+            queries,
+            seed,
+            check_against,
+        } => {
+            if let Some(LceOracle::Naive) = check_against {
+                info!("Checking {:?} against naive LCE", algo);
+                exit_on_mismatches(
+                    suffix_array::check_against_naive(text, *algo, *queries, *seed),
+                    "naive",
+                );
+            }
+
+            (0..output.repeat).try_for_each(|run| {
+                suffix_array::benchmark(text, *algo, *queries, *seed, output.output(run))
+            })
+        }
+        Command::Bitvector {
+            len,
+            density,
+            queries,
+            seed,
+            repeat,
+            check_against,
+        } => {
+            if *len == 0 || !(0.0..=1.0).contains(density) {
+                eprintln!("Need len > 0 and density between 0 and 1");
+                std::process::exit(1);
+            }
+
+            for run in 0..*repeat {
+                info!("Run {}/{}", run + 1, repeat);
+
+                let mismatches =
+                    bitvector::benchmark_random(*len, *density, *queries, *seed, *check_against);
+                exit_on_mismatches(Ok(mismatches), &format!("{:?}", check_against));
+            }
+
+            Ok(())
+        }
+        Command::Generate {
+            kind,
+            output,
+            size,
+            queries,
+            max_value,
+            values,
+            query_distribution,
+            seed,
+        } => {
+            if *size == 0 {
+                eprintln!("Need size > 0");
+                std::process::exit(1);
+            }
+
+            let options = generator::GeneratorOptions {
+                size: *size,
+                queries: *queries,
+                max_value: *max_value,
+                values: *values,
+                query_distribution: *query_distribution,
+                seed: *seed,
+            };
+
+            match kind {
+                InstanceKind::Pd => {
+                    instances::write_pd_instance(output, &generator::generate_pd(&options))
+                }
+                InstanceKind::Rmq => {
+                    instances::write_rmq_instance(output, &generator::generate_rmq(&options))
+                }
+                InstanceKind::Lca => {
+                    instances::write_lca_instance(output, &generator::generate_lca(&options))
+                }
+            }
+        }
+        Command::Differential {
+            cases,
+            max_size,
+            queries,
+            seed,
+            algo,
+        } => {
+            if *max_size == 0 {
+                eprintln!("Need max-size > 0");
+                std::process::exit(1);
+            }
+
+            let algorithms = match algo.is_empty() {
+                true => rmq::algorithms(),
+                false => algo.clone(),
+            };
+
+            let failures = rmq::run_algorithms(
+                &algorithms,
+                &rmq::DifferentialOptions {
+                    cases: *cases,
+                    max_size: *max_size,
+                    queries: *queries,
+                    seed: *seed,
+                },
+            );
+
+            for failure in &failures {
+                eprintln!("{}", failure);
+            }
+            exit_on_mismatches(Ok(failures.len() as u64), "naive-slow");
+
+            println!("All {} algorithms agree with naive-slow", algorithms.len());
+
+            Ok(())
+        }
+        Command::CartesianTree { input, output } => {
+            rmq::write_cartesian_tree(input, output).map(|tree| {
+                println!(
+                    "Wrote Cartesian tree of {} numbers with root {:?} to {}",
+                    tree.len(),
+                    tree.root(),
+                    output.display()
+                );
+            })
+        }
+        Command::ConvertPd { input, output } => {
+            convert(instances::convert_pd_to_binary, input, output)
+        }
+        Command::ConvertRmq { input, output } => {
+            convert(instances::convert_rmq_to_binary, input, output)
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}: {}", failure_message(&cli.command), err);
         std::process::exit(1);
     }
 
-    let command = &args[1];
-    let file_path: &Path = Path::new(&args[2]);
-    let out_filepath = &args[3];
+    Ok(())
+}
 
-    if !file_path.exists() {
-        println!("File {} does not exist", file_path.display());
-        std::process::exit(1);
+// What went wrong when a subcommand fails with an instance error: generate
+// and convert-* write instances, every other subcommand reads one.
+fn failure_message(command: &Command) -> &'static str {
+    match command {
+        Command::Generate { .. } => "Could not write instance",
+        Command::ConvertPd { .. } | Command::ConvertRmq { .. } => "Could not convert instance",
+        Command::CartesianTree { .. } => "Could not write Cartesian tree",
+        _ => "Could not read instance",
+    }
+}
+
+// Exits on a structure that cannot be built, leaves instance errors to be
+// reported like for every other subcommand.
+fn exit_on_build_error<T>(
+    result: Result<T, rmq::BenchmarkError>,
+) -> Result<T, instances::InstanceError> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(rmq::BenchmarkError::Instance(err)) => Err(err),
+        Err(err @ rmq::BenchmarkError::Build(_)) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
     }
+}
 
-    match command.as_ref() {
-        "pd" => predecessor::benchmark_and_check(file_path, None, Some(out_filepath.clone())),
-        "rmq" => rmq::rmq(file_path, Some(out_filepath.clone())),
-        _ => {
-            println!("Unknown command");
+fn exit_on_mismatches(mismatches: Result<u64, instances::InstanceError>, oracle: &str) {
+    match mismatches {
+        Ok(0) => {}
+        Ok(mismatches) => {
+            eprintln!("{} answers differ from {}", mismatches, oracle);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("Could not read instance: {}", err);
             std::process::exit(1);
         }
     }
+}
+
+fn convert(
+    converter: fn(&Path, &Path) -> Result<u64, instances::InstanceError>,
+    input: &Path,
+    output: &Path,
+) -> Result<(), instances::InstanceError> {
+    let queries = converter(input, output)?;
+    println!("Wrote {} queries to {}", queries, output.display());
 
     Ok(())
 }
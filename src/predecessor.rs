@@ -6,12 +6,12 @@ use super::report;
 use std::path::Path;
 use std::time::Instant;
 
-use crate::bitvector::MyError;
+use crate::instances::InstanceError;
+#[cfg(test)]
 use crate::instances::PDInstance;
 use crate::malloc_size_of::MallocSizeOf;
 use crate::malloc_size_of::MallocSizeOfOps;
-
-use super::debug::DEBUG;
+use log::{debug, info, trace};
 
 #[derive(MallocSizeOf)]
 struct PD {
@@ -56,11 +56,12 @@ impl PD {
         // Its only used for pi_divisor and for initial splitting into lower.
         // But since it was zero, ...?
         //
-        let u = numbers[numbers.len() - 1] as u64;
+        // An empty PD still gets one bucket, pred checks for it first.
+        let u = numbers.last().copied().unwrap_or(0);
 
         // Number of numbers.
         let n = numbers.len();
-        let n_float = numbers.len() as f64;
+        let n_float = n.max(1) as f64;
 
         // n bit intgers
 
@@ -86,20 +87,16 @@ impl PD {
         //
         // Alternative: Use sparse bitvector.
         while ((2 * n + 1) as u64) < (u / 2u64.pow(upper_bits as u32)) {
-            if DEBUG {
-                println!("Not enough space in upper_vec for upper_bits");
-            }
+            debug!("Not enough space in upper_vec for upper_bits");
 
             upper_bits = upper_bits + 10;
             lower_bits = lower_bits + 10;
         }
 
-        if DEBUG {
-            println!(
-                "upper_bits: {}, lower_bits: {}, u: {}, n: {}",
-                upper_bits, lower_bits, u, n
-            );
-        }
+        debug!(
+            "upper_bits: {}, lower_bits: {}, u: {}, n: {}",
+            upper_bits, lower_bits, u, n
+        );
 
         // How do I handle upper_bits = 0?
         // a)     Use 1 by default.
@@ -118,18 +115,16 @@ impl PD {
             // Basically gets used
             let pi = (number / pi_divisor) as usize;
 
-            if DEBUG {
-                println!(
-                "Setting to true: number: {} i: {} pi: {} pi+i: {} pi_divisor: {}, upper_bits: {}, lower_bits: {}",
-                number,
-                i,
-                pi,
-                pi + i,
-                pi_divisor,
-                upper_bits,
-                lower_bits
+            debug!(
+            "Setting to true: number: {} i: {} pi: {} pi+i: {} pi_divisor: {}, upper_bits: {}, lower_bits: {}",
+            number,
+            i,
+            pi,
+            pi + i,
+            pi_divisor,
+            upper_bits,
+            lower_bits
             );
-            }
 
             // Set upper bit to true.
             upper_vec[pi + i] = true;
@@ -145,16 +140,14 @@ impl PD {
             }
         }
 
-        if DEBUG {
-            println!(
-                "PD::new - numbers: {} upper: {:?} lower: {:?} upper_bits: {} lower_bits: {}",
-                numbers.len(),
-                upper_vec.len(),
-                lower_vec.len(),
-                upper_bits,
-                lower_bits
-            );
-        }
+        debug!(
+            "PD::new - numbers: {} upper: {:?} lower: {:?} upper_bits: {} lower_bits: {}",
+            numbers.len(),
+            upper_vec.len(),
+            lower_vec.len(),
+            upper_bits,
+            lower_bits
+        );
 
         return Self {
             numbers_count: n as u64,
@@ -168,15 +161,13 @@ impl PD {
     // Lower bit access:
     // i - 1 bits davor * Anzahlå-bits die die zahlen lang sind
     // -> einzelne bits lesen.
-    pub fn access(&self, i: u64) -> Result<u64, MyError> {
-        if DEBUG {
-            println!(
-                "access({}) - upper_select1({}): {}",
-                i,
-                i,
-                self.upper.select1(i)?
-            );
-        }
+    pub fn access(&self, i: u64) -> u64 {
+        trace!(
+            "access({}) - upper_select1({}): {:?}",
+            i,
+            i + 1,
+            self.upper.select1(i + 1)
+        );
 
         assert!(i < self.numbers_count, "i must be smaller than n");
 
@@ -189,9 +180,6 @@ impl PD {
         // Except.. the slides show select1(5) to return the 5th 1 and not
         // the 6th 1.
 
-        // i == 0 works by default because select1(0) returns 0 by default,
-        // despite there being no 1.
-
         // But.. removing the - i here or using the i does not help much,
         // because the i does not care about 0s in between (the distinction
         // between in-group 1s and between-group 0s).
@@ -201,21 +189,13 @@ impl PD {
         // the other?
         //
         // Switched upper_bits and lower_bits.
-        let upper_part: u64;
-
-        if i == 0 {
-            upper_part = self.upper.select1(i + 1)? - i;
-        } else {
-            // Working around peculiarity that select1 is 1-based,
-            // while returning 0 for select1(0).
-            //
-            // Except: i + 1 for i == self.number_count goes over the limit.
-            if i == self.numbers_count {
-                upper_part = self.upper.select1(i + 1)? - i;
-            } else {
-                upper_part = self.upper.select1(i + 1)? - i;
-            }
-        }
+        //
+        // select1 is 1-based, so the i-th number (0-based) is the (i + 1)-th 1.
+        let upper_part = self
+            .upper
+            .select1(i + 1)
+            .expect("upper has a 1 for every number")
+            - i;
 
         // Get lower bits from self.lower.
         let lower_bits =
@@ -223,14 +203,14 @@ impl PD {
 
         let lower_part = Self::bits_to_u64(lower_bits);
 
-        if DEBUG {
-            println!(
-                "access({}) - upper_part: {} lower_part: {}",
-                i, upper_part, lower_part
-            );
-        }
+        trace!(
+            "access({}) - upper_part: {} lower_part: {}",
+            i,
+            upper_part,
+            lower_part
+        );
 
-        return Ok((upper_part << (self.upper_bits) | lower_part) as u64);
+        return (upper_part << (self.upper_bits) | lower_part) as u64;
     }
 
     fn bits_to_u64(bits: &[bool]) -> u64 {
@@ -253,7 +233,13 @@ impl PD {
         return v - 1;
     }
 
-    pub fn pred(&self, i: u64) -> Result<u64, MyError> {
+    // Largest number at most i, u64::MAX if there is none.
+    pub fn pred(&self, i: u64) -> u64 {
+        // No numbers, no predecessor.
+        if self.numbers_count == 0 {
+            return u64::MAX;
+        }
+
         // Split into lower and upper.
         //
         // Quqestion: MSB is supposed to include zeroes and ones both, right?
@@ -261,7 +247,18 @@ impl PD {
         let (lower, msb) = self.split(i);
 
         // Index in upper vector of the start of the bucket.
-        let p = self.upper.select0(msb as u64)?;
+        //
+        // select0 is 1-based, so bucket 0 starts at the very front. If there
+        // is no msb-th 0, then i lies above all buckets and the biggest number
+        // is its predecessor.
+        let p = if msb == 0 {
+            0
+        } else {
+            match self.upper.select0(msb as u64) {
+                Some(p) => p,
+                None => return self.access(self.numbers_count - 1),
+            }
+        };
 
         // Indexes up to and including the first in the bucket
         //
@@ -287,9 +284,8 @@ impl PD {
         // If ith is the last in the original numbers, then bucket is empty
         // anyway. Except.. if i-th is much earlier, then.. .
         if ith_in_original_numbers == self.numbers_count - 1 {
-            if DEBUG {
-                println!("pred exit: last number - p: {} msb: {}", p, msb);
-            }
+            trace!("pred exit: last number - p: {} msb: {}", p, msb);
+
             return self.access(ith_in_original_numbers);
         }
 
@@ -298,9 +294,8 @@ impl PD {
         //
         // But.. do I really check that that way?
         if self.upper.get(p + 1) == false {
-            if DEBUG {
-                println!("pred exit: bucket empty");
-            }
+            trace!("pred exit: bucket empty");
+
             // We are in a higher bucket, so the bucket was empty, so we need to
             // take the last from a smaller bucket and return that.
             //
@@ -316,10 +311,14 @@ impl PD {
 
         // Get the next bucket.
         //
-        // But.. how do I handle there not being any bucket anymore?
+        // If there is no bucket anymore, the bucket reaches up to the end of
+        // upper.
         //
         // +1 here because select0 is 1-based.
-        let next_bucket_p = self.upper.select0(msb as u64 + 1)?;
+        let next_bucket_p = self
+            .upper
+            .select0(msb as u64 + 1)
+            .unwrap_or(self.upper.len());
 
         // Indexes up to and including the last in the bucket
         //
@@ -327,12 +326,15 @@ impl PD {
         // previous bucket before next_bucket_p.
         let last_in_bucket_ith = self.upper.rank1(next_bucket_p) - 1;
 
-        if DEBUG {
-            println!(
-                "pred({}) - msb: {} p: {} ith: {} numbers_count: {} last_in_bucket_ith: {}",
-                i, msb, p, ith_in_original_numbers, self.numbers_count, last_in_bucket_ith
-            );
-        }
+        trace!(
+            "pred({}) - msb: {} p: {} ith: {} numbers_count: {} last_in_bucket_ith: {}",
+            i,
+            msb,
+            p,
+            ith_in_original_numbers,
+            self.numbers_count,
+            last_in_bucket_ith
+        );
 
         // This bucket is non-empty.
         //
@@ -356,10 +358,9 @@ impl PD {
         //
         if self.get_lower_bits(0) > lower && ith_in_original_numbers == 0 {
             // d: Lower is smaller than the first in the initial array.
-            if DEBUG {
-                println!("d) lower is smaller than 1st element.");
-            }
-            return Ok(u64::MAX);
+            trace!("d) lower is smaller than 1st element.");
+
+            return u64::MAX;
         }
 
         //
@@ -374,18 +375,19 @@ impl PD {
             let mid = start_original + (end_original - start_original) / 2;
             let bits_number = self.get_lower_bits(mid);
 
-            if DEBUG {
-                println!(
-                    "i={} mid: {}, start_original: {}, end_original: {} bits_number: {} lowr: {}",
-                    i, mid, start_original, end_original, bits_number, lower,
-                );
-            }
+            trace!(
+                "i={} mid: {}, start_original: {}, end_original: {} bits_number: {} lowr: {}",
+                i,
+                mid,
+                start_original,
+                end_original,
+                bits_number,
+                lower,
+            );
 
             if bits_number == lower {
                 // c)
-                if DEBUG {
-                    println!("c)");
-                }
+                trace!("c)");
 
                 //
                 // Fast exit here because the other cases only matter
@@ -395,47 +397,40 @@ impl PD {
             }
 
             if bits_number > lower {
-                if DEBUG {
-                    println!("i={} Decrementing end_original", i);
-                }
+                trace!("i={} Decrementing end_original", i);
+
                 end_original = mid - 1;
 
-                if DEBUG {
-                    println!("Updating latest found to mid={}", mid);
-                }
+                trace!("Updating latest found to mid={}", mid);
+
                 latest_found_original_bigger_than_lower = Some(mid);
             } else {
-                if DEBUG {
-                    println!("i={} Incrementing", i);
-                }
+                trace!("i={} Incrementing", i);
+
                 start_original = mid + 1;
             }
         }
 
-        if DEBUG {
-            println!(
-                "i={} Done searching found_ith_orig: {:?}",
-                i, latest_found_original_bigger_than_lower
-            );
-        }
+        trace!(
+            "i={} Done searching found_ith_orig: {:?}",
+            i,
+            latest_found_original_bigger_than_lower
+        );
 
         match latest_found_original_bigger_than_lower {
             Some(found_in_original) => {
                 // a)
-                if DEBUG {
-                    println!("a) i: {}", found_in_original);
-                }
+                trace!("a) i: {}", found_in_original);
 
                 return self.access(Self::decrement_min_zero(found_in_original));
             }
             None => {
                 // b)
-                if DEBUG {
-                    println!(
-                        "b: lower: {} last_in_bucket_ith: {}",
-                        lower, last_in_bucket_ith
-                    );
-                }
+                trace!(
+                    "b: lower: {} last_in_bucket_ith: {}",
+                    lower,
+                    last_in_bucket_ith
+                );
 
                 return self.access(last_in_bucket_ith);
             }
@@ -453,7 +448,8 @@ impl PD {
     }
 }
 
-fn benchmark(instance: PDInstance, out: Option<String>) {
+#[cfg(test)]
+fn benchmark(instance: PDInstance, out: Option<report::Output>) {
     // Clone numbers because we sort them.
     let mut numbers = instance.numbers.clone();
 
@@ -463,35 +459,125 @@ fn benchmark(instance: PDInstance, out: Option<String>) {
 
     let pd = PD::new(&mut numbers);
 
+    let mut measurement = report::Measurement::new("pd", start.elapsed());
+
     let queries_count = instance.queries.len();
 
     for (i, query) in instance.queries.iter().enumerate() {
-        got_all.push(pd.pred(*query).unwrap());
+        let start = Instant::now();
+        let got = pd.pred(*query);
+        measurement.record_query(start.elapsed());
 
-        if DEBUG {
-            if i % 100 == 0 {
-                println!("Query nr {}/{}", i, queries_count);
-            }
+        got_all.push(got);
+
+        if i % 100 == 0 {
+            debug!("Query nr {}/{}", i, queries_count);
         }
     }
 
-    let duration = start.elapsed();
-
     //
     // Measure malloc.
     //
     let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
-    let size = pd.size_of(&mut ops);
+    measurement.space = pd.size_of(&mut ops);
 
     report::write_out(out, got_all);
 
-    report::report("pd", duration, size);
+    report::report(&measurement);
 }
 
-pub fn benchmark_and_check(path: &Path, want: Option<Vec<u64>>, out: Option<String>) {
-    println!("pd");
+// Like benchmark, but reads the queries one at a time while answering them
+// and writes every answer right away. Memory stays at the size of the
+// numbers and the PD, however many queries the instance has.
+pub fn benchmark_streaming(path: &Path, out: Option<report::Output>) -> Result<(), InstanceError> {
+    info!("pd");
+
+    let instance = instances::stream_pd_instance(path)?;
+    let mut numbers = instance.numbers;
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    let pd = PD::new(&mut numbers);
 
-    let instance = instances::read_pd_instance(path).unwrap();
+    let mut measurement = report::Measurement::new("pd", start.elapsed());
+
+    for (i, query) in instance.queries.enumerate() {
+        let query = query?;
+
+        let start = Instant::now();
+        let got = pd.pred(query);
+        measurement.record_query(start.elapsed());
+
+        writer.push(got);
+
+        if i % 100 == 0 {
+            debug!("Query nr {}", i);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = pd.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
+}
+
+// Predecessor by binary search over the sorted numbers, u64::MAX if there
+// is none. Oracle for PD.
+pub fn pred_naive(sorted: &[u64], i: u64) -> u64 {
+    match sorted.partition_point(|number| *number <= i) {
+        0 => u64::MAX,
+        p => sorted[p - 1],
+    }
+}
+
+// Answers every query of the instance with PD and pred_naive. Returns the
+// number of mismatches, the first few are printed.
+pub fn check_against_naive(path: &Path) -> Result<u64, InstanceError> {
+    let instance = instances::stream_pd_instance(path)?;
+
+    let mut sorted = instance.numbers.clone();
+    sorted.sort_unstable();
+
+    let mut numbers = instance.numbers;
+    let pd = PD::new(&mut numbers);
+
+    let mut mismatches = 0;
+
+    for (i, query) in instance.queries.enumerate() {
+        let query = query?;
+
+        let want = pred_naive(&sorted, query);
+        let got = pd.pred(query);
+
+        if want != got {
+            if mismatches < 10 {
+                eprintln!(
+                    "Mismatch in query nr {}: {} want: {} got: {}",
+                    i, query, want, got
+                );
+            }
+            mismatches += 1;
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+pub fn benchmark_and_check(
+    path: &Path,
+    want: Option<Vec<u64>>,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    info!("pd");
+
+    let instance = instances::read_pd_instance(path)?;
 
     // Check correctness.
     if let Some(want) = want {
@@ -499,24 +585,41 @@ pub fn benchmark_and_check(path: &Path, want: Option<Vec<u64>>, out: Option<Stri
         let pd = PD::new(&mut numbers);
 
         for (i, query) in instance.queries.clone().iter().enumerate() {
-            if DEBUG {
-                println!("Query nr {}: {}", i, query);
-            }
+            trace!("Query nr {}: {}", i, query);
 
-            let got = pd.pred(*query).unwrap();
+            let got = pd.pred(*query);
             assert_eq!(want[i], got, "Query nr {}: {}", i, query);
         }
     }
 
     // Start benchmark
     benchmark(instance, out);
+
+    Ok(())
+}
+
+#[test]
+fn testing_pd_check_against_naive() {
+    let path: &Path = Path::new("testdata/predecessor_examples/predecessor_example_4.txt");
+
+    assert_eq!(0, check_against_naive(path).unwrap());
+    assert_eq!(u64::MAX, pred_naive(&[1, 2, 4, 7], 0));
+    assert_eq!(4, pred_naive(&[1, 2, 4, 7], 6));
+}
+
+#[test]
+fn testing_pd_empty() {
+    let pd = PD::new(&mut vec![]);
+
+    assert_eq!(u64::MAX, pd.pred(0));
+    assert_eq!(u64::MAX, pd.pred(u64::MAX));
 }
 
 #[test]
 fn testing_pd_access() {
     let pd = PD::new(&mut vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
 
-    let got: Vec<u64> = (0..10).map(|i| pd.access(i).unwrap()).collect();
+    let got: Vec<u64> = (0..10).map(|i| pd.access(i)).collect();
 
     assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9], got);
 }
@@ -527,28 +630,28 @@ fn testing_pd_test() {
 
     let want = vec![u64::MAX, 1, 2, 2, 4, 4, 4, 7, 7, 7, 7];
 
-    benchmark_and_check(path, Some(want), None);
+    benchmark_and_check(path, Some(want), None).unwrap();
 }
 
 #[test]
 fn testing_pd_benchmark1() {
     let path = Path::new("testdata/predecessor_examples/predecessor_example_1.txt");
 
-    benchmark_and_check(path, None, None);
+    benchmark_and_check(path, None, None).unwrap();
 }
 
 #[test]
 fn testing_pd_benchmark2() {
     let path = Path::new("testdata/predecessor_examples/predecessor_example_2.txt");
 
-    benchmark_and_check(path, None, None);
+    benchmark_and_check(path, None, None).unwrap();
 }
 
 #[test]
 fn testing_pd_benchmark3() {
     let path = Path::new("testdata/predecessor_examples/predecessor_example_3.txt");
 
-    benchmark_and_check(path, None, None);
+    benchmark_and_check(path, None, None).unwrap();
 }
 
 #[test]
@@ -1,44 +1,499 @@
-use std::io::Write;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use std::{fs::OpenOptions, time::Duration};
 
-pub fn report(algo: &str, time: Duration, space: usize) {
-    println!(
-        "RESULT algo={} name=Fabian_Sturm time={} space={}",
-        algo,
-        time.as_millis(),
-        space
-    );
-}
-pub fn write_out(out: Option<String>, got_all: Vec<u64>) {
-    match out {
-        Some(out) => {
-            //
-            // Write output.
-            //
-            println!("Writing output: out={}", out);
+use log::info;
+
+// How benchmark results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ReportFormat {
+    // The classic `RESULT algo=... name=... time=... space=...` line.
+    Text,
+    // One JSON object per line.
+    Json,
+    // CSV, with a header line before the first result.
+    Csv,
+}
+
+pub struct ReportOptions {
+    pub format: ReportFormat,
+    // Name of the run, e.g. machine or commit, to tell results apart. Empty
+    // means the algorithm name is used.
+    pub label: String,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            format: ReportFormat::Text,
+            label: String::new(),
+        }
+    }
+}
+
+static OPTIONS: OnceLock<ReportOptions> = OnceLock::new();
+static CSV_HEADER_PRINTED: AtomicBool = AtomicBool::new(false);
+
+// Sets how all following results are reported. Only the first call has an
+// effect, later calls return false.
+pub fn set_options(options: ReportOptions) -> bool {
+    OPTIONS.set(options).is_ok()
+}
+
+fn options() -> &'static ReportOptions {
+    OPTIONS.get_or_init(ReportOptions::default)
+}
+
+// Histogram of query latencies in nanoseconds.
+//
+// Every power of two is split into 16 buckets, so memory stays constant no
+// matter how many queries are run, and a percentile is off by at most 1/16
+// of its value.
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = 4;
+
+#[allow(dead_code)]
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        LatencyHistogram {
+            counts: vec![0; Self::bucket(u64::MAX) + 1],
+            total: 0,
+            max: 0,
+        }
+    }
+
+    fn bucket(ns: u64) -> usize {
+        if ns < SUB_BUCKETS {
+            return ns as usize;
+        }
+
+        let exponent = 63 - ns.leading_zeros();
+        let sub_bucket = (ns >> (exponent - SUB_BUCKET_BITS)) & (SUB_BUCKETS - 1);
+
+        ((exponent - SUB_BUCKET_BITS + 1) as u64 * SUB_BUCKETS + sub_bucket) as usize
+    }
+
+    // Largest latency falling into bucket.
+    fn bucket_upper(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+
+        let shift = bucket / SUB_BUCKETS - 1;
+        let lower = (SUB_BUCKETS + bucket % SUB_BUCKETS) << shift;
+
+        lower + ((1 << shift) - 1)
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let ns = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+
+        self.counts[Self::bucket(ns)] += 1;
+        self.total += 1;
+        self.max = self.max.max(ns);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    // Latency that fraction (0.0 to 1.0) of all recorded latencies are at
+    // most, or 0 if nothing was recorded.
+    pub fn percentile(&self, fraction: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+
+        let rank = ((fraction * self.total as f64).ceil() as u64).clamp(1, self.total);
+
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_upper(bucket).min(self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+}
+
+// Everything measured in one benchmark run.
+pub struct Measurement {
+    pub algo: String,
+    pub construction: Duration,
+    // Sum of all query latencies.
+    pub queries: Duration,
+    pub latencies: LatencyHistogram,
+    // Heap space of the data structure in bytes.
+    pub space: usize,
+}
+
+impl Measurement {
+    pub fn new(algo: impl Into<String>, construction: Duration) -> Self {
+        Measurement {
+            algo: algo.into(),
+            construction,
+            queries: Duration::ZERO,
+            latencies: LatencyHistogram::new(),
+            space: 0,
+        }
+    }
+
+    pub fn record_query(&mut self, latency: Duration) {
+        self.queries += latency;
+        self.latencies.record(latency);
+    }
+}
+
+const CSV_HEADER: &str = "label,algo,queries,construction_ns,query_ns,\
+latency_p50_ns,latency_p90_ns,latency_p99_ns,latency_max_ns,space_bytes";
+
+pub fn report(measurement: &Measurement) {
+    let options = options();
+
+    println!("{}", format_report(measurement, options));
+}
+
+fn format_report(measurement: &Measurement, options: &ReportOptions) -> String {
+    let latencies = &measurement.latencies;
+    let label = if options.label.is_empty() {
+        measurement.algo.as_str()
+    } else {
+        &options.label
+    };
+
+    match options.format {
+        ReportFormat::Text => format!(
+            "RESULT algo={} name={} time={} space={}",
+            measurement.algo,
+            label,
+            (measurement.construction + measurement.queries).as_millis(),
+            measurement.space
+        ),
+        ReportFormat::Json => format!(
+            "{{\"label\":{},\"algo\":{},\"queries\":{},\"construction_ns\":{},\
+\"query_ns\":{},\"latency_ns\":{{\"p50\":{},\"p90\":{},\"p99\":{},\"max\":{}}},\
+\"space_bytes\":{}}}",
+            json_string(label),
+            json_string(&measurement.algo),
+            latencies.count(),
+            measurement.construction.as_nanos(),
+            measurement.queries.as_nanos(),
+            latencies.percentile(0.5),
+            latencies.percentile(0.9),
+            latencies.percentile(0.99),
+            latencies.max(),
+            measurement.space
+        ),
+        ReportFormat::Csv => {
+            let row = format!(
+                "{},{},{},{},{},{},{},{},{},{}",
+                csv_field(label),
+                csv_field(&measurement.algo),
+                latencies.count(),
+                measurement.construction.as_nanos(),
+                measurement.queries.as_nanos(),
+                latencies.percentile(0.5),
+                latencies.percentile(0.9),
+                latencies.percentile(0.99),
+                latencies.max(),
+                measurement.space
+            );
+
+            if CSV_HEADER_PRINTED.swap(true, Ordering::Relaxed) {
+                row
+            } else {
+                format!("{}\n{}", CSV_HEADER, row)
+            }
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// How answers are written to the output file.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum OutputFormat {
+    // One answer per line.
+    Lines,
+    // All answers on one line, separated by ", ".
+    Comma,
+    // JSON array of numbers.
+    Json,
+    // Every answer as little-endian u64, without any separator.
+    Binary,
+}
+
+pub struct Output {
+    pub path: String,
+    pub format: OutputFormat,
+}
+
+// Writes answers to the output file as they are computed, so they never
+// have to be collected in memory.
+//
+// The file is truncated when opened, so nothing of a previous, longer
+// output survives.
+pub struct AnswerWriter {
+    out: Option<(String, OutputFormat, BufWriter<File>)>,
+    written: u64,
+}
+
+impl AnswerWriter {
+    pub fn new(out: Option<Output>) -> Self {
+        let out = out.map(|out| {
+            info!("Writing output: out={}", out.path);
 
             let file = OpenOptions::new()
                 .write(true)
                 .create(true)
-                .append(false)
-                .open(&out);
-            if file.is_err() {
-                println!(
-                    "Could not open output file: {} error: {}",
-                    out,
-                    file.unwrap_err()
-                );
+                .truncate(true)
+                .open(&out.path);
+            match file {
+                Ok(file) => (out.path, out.format, BufWriter::new(file)),
+                Err(err) => {
+                    eprintln!("Could not open output file: {} error: {}", out.path, err);
+                    std::process::exit(1);
+                }
+            }
+        });
+
+        AnswerWriter { out, written: 0 }
+    }
+
+    pub fn push(&mut self, value: u64) {
+        if let Some((out, format, writer)) = &mut self.out {
+            let first = self.written == 0;
+
+            let result = match format {
+                OutputFormat::Lines => writeln!(writer, "{}", value),
+                OutputFormat::Comma if first => write!(writer, "{}", value),
+                OutputFormat::Comma => write!(writer, ", {}", value),
+                OutputFormat::Json if first => write!(writer, "[{}", value),
+                OutputFormat::Json => write!(writer, ",{}", value),
+                OutputFormat::Binary => writer.write_all(&value.to_le_bytes()),
+            };
+
+            if let Err(err) = result {
+                eprintln!("Could not write output file: {} error: {}", out, err);
+                std::process::exit(1);
+            }
+        }
+
+        self.written += 1;
+    }
+
+    // Closes the JSON array and flushes the output file. Errors while
+    // flushing would otherwise be lost when the writer is dropped.
+    pub fn finish(self) {
+        if let Some((out, format, mut writer)) = self.out {
+            let result = match format {
+                OutputFormat::Json if self.written == 0 => writer.write_all(b"[]\n"),
+                OutputFormat::Json => writer.write_all(b"]\n"),
+                _ => Ok(()),
+            }
+            .and_then(|_| writer.flush());
+
+            if let Err(err) = result {
+                eprintln!("Could not write output file: {} error: {}", out, err);
                 std::process::exit(1);
             }
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn write_out(out: Option<Output>, got_all: Vec<u64>) {
+    let mut writer = AnswerWriter::new(out);
+
+    for value in got_all {
+        writer.push(value);
+    }
+
+    writer.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(name: &str, format: OutputFormat, values: Vec<u64>) -> Vec<u8> {
+        let path =
+            std::env::temp_dir().join(format!("rmq_project_out_{}_{}", name, std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        // Longer content from an earlier run must not survive.
+        std::fs::write(&path, "9".repeat(100)).unwrap();
+
+        write_out(
+            Some(Output {
+                path: path.clone(),
+                format,
+            }),
+            values,
+        );
+
+        std::fs::read(&path).unwrap()
+    }
+
+    #[test]
+    fn testing_output_formats() {
+        assert_eq!(
+            b"1\n22\n3\n".to_vec(),
+            written("lines", OutputFormat::Lines, vec![1, 22, 3])
+        );
+        assert_eq!(
+            b"1, 22, 3".to_vec(),
+            written("comma", OutputFormat::Comma, vec![1, 22, 3])
+        );
+        assert_eq!(
+            b"[1,22,3]\n".to_vec(),
+            written("json", OutputFormat::Json, vec![1, 22, 3])
+        );
+        assert_eq!(
+            b"[]\n".to_vec(),
+            written("json_empty", OutputFormat::Json, vec![])
+        );
+        assert_eq!(
+            b"".to_vec(),
+            written("comma_empty", OutputFormat::Comma, vec![])
+        );
+
+        let binary = written("binary", OutputFormat::Binary, vec![1, u64::MAX]);
+        assert_eq!(16, binary.len());
+        assert_eq!(1, u64::from_le_bytes(binary[..8].try_into().unwrap()));
+        assert_eq!(
+            u64::MAX,
+            u64::from_le_bytes(binary[8..].try_into().unwrap())
+        );
+    }
+
+    #[test]
+    fn testing_latency_histogram() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(0, histogram.percentile(0.5));
+
+        for ns in 1..=1000 {
+            histogram.record(Duration::from_nanos(ns));
+        }
+
+        assert_eq!(1000, histogram.count());
+        assert_eq!(1000, histogram.max());
+        assert_eq!(1000, histogram.percentile(1.0));
+        assert_eq!(1, histogram.percentile(0.0));
+
+        // Off by at most 1/16.
+        for (fraction, want) in [(0.5, 500.0), (0.9, 900.0), (0.99, 990.0)] {
+            let got = histogram.percentile(fraction) as f64;
+            assert!(
+                got >= want && got <= want * 17.0 / 16.0,
+                "{} {}",
+                fraction,
+                got
+            );
+        }
+
+        for ns in [0, 15, 16, 17, 31, 32, 1 << 40, u64::MAX] {
+            let bucket = LatencyHistogram::bucket(ns);
+            assert!(ns <= LatencyHistogram::bucket_upper(bucket), "ns={}", ns);
+            if bucket > 0 {
+                assert!(ns > LatencyHistogram::bucket_upper(bucket - 1), "ns={}", ns);
+            }
+        }
+    }
+
+    #[test]
+    fn testing_report_formats() {
+        let mut measurement = Measurement::new("rmq", Duration::from_millis(3));
+        measurement.record_query(Duration::from_nanos(10));
+        measurement.record_query(Duration::from_nanos(12));
+        measurement.space = 64;
+
+        let options = ReportOptions {
+            format: ReportFormat::Text,
+            label: "run".to_string(),
+        };
+        assert_eq!(
+            "RESULT algo=rmq name=run time=3 space=64",
+            format_report(&measurement, &options)
+        );
+
+        let options = ReportOptions {
+            format: ReportFormat::Json,
+            label: "a\"b".to_string(),
+        };
+        assert_eq!(
+            "{\"label\":\"a\\\"b\",\"algo\":\"rmq\",\"queries\":2,\"construction_ns\":3000000,\
+\"query_ns\":22,\"latency_ns\":{\"p50\":10,\"p90\":12,\"p99\":12,\"max\":12},\
+\"space_bytes\":64}",
+            format_report(&measurement, &options)
+        );
+
+        let options = ReportOptions {
+            format: ReportFormat::Csv,
+            label: "x,y".to_string(),
+        };
+        let csv = format_report(&measurement, &options);
+        assert!(csv.ends_with("\"x,y\",rmq,2,3000000,22,10,12,12,12,64"));
+
+        assert_eq!(
+            "RESULT algo=rmq name=rmq time=3 space=64",
+            format_report(&measurement, &ReportOptions::default())
+        );
+    }
 
-            let out_string = got_all
-                .iter()
-                .map(|value| value.to_string())
-                .collect::<Vec<_>>()
-                .join(", ");
+    #[test]
+    fn testing_report_algorithm_name() {
+        use crate::rmq::Algorithm;
 
-            file.unwrap().write_all(out_string.as_bytes()).unwrap();
+        for (algorithm, want) in [
+            (Algorithm::NaiveSlow, "naive-slow"),
+            (Algorithm::Sparse, "sparse"),
+            (Algorithm::SpanningBlocks, "spanning-blocks"),
+        ] {
+            let measurement = Measurement::new(algorithm.name(), Duration::from_millis(1));
+            assert_eq!(
+                format!("RESULT algo={} name={} time=1 space=0", want, want),
+                format_report(&measurement, &ReportOptions::default())
+            );
         }
-        None => {}
     }
 }
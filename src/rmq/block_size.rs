@@ -0,0 +1,241 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::heapsize;
+use super::rmq_spanning_blocks::{default_block_size, RMQSpanningBlocks, MAX_BLOCK_SIZE};
+use super::RMQError;
+
+use crate::malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use log::{debug, info};
+
+// What tune_block_size minimizes.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Objective {
+    // Construction time.
+    Build,
+    // Mean time of a query.
+    Query,
+    // Heap space of the structure.
+    Space,
+    // Construction plus all queries, the time of one benchmark run.
+    Total,
+}
+
+// Measurements of one block size.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub block_size: usize,
+    pub construction: Duration,
+    // Sum over all queries.
+    pub queries: Duration,
+    pub query_count: usize,
+    pub space: usize,
+}
+
+impl Candidate {
+    fn cost(&self, objective: Objective) -> u128 {
+        match objective {
+            Objective::Build => self.construction.as_nanos(),
+            Objective::Query => self.queries.as_nanos() / self.query_count.max(1) as u128,
+            Objective::Space => self.space as u128,
+            Objective::Total => (self.construction + self.queries).as_nanos(),
+        }
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "block_size={} construction_ms={} query_ns={} space={}",
+            self.block_size,
+            self.construction.as_millis(),
+            self.queries.as_nanos() / self.query_count.max(1) as u128,
+            self.space
+        )
+    }
+}
+
+// Block sizes up to twice the default for n numbers. Larger ones have far
+// more possible Cartesian trees than blocks, so each block gets its own
+// table of block_size^2 bytes, which takes longer to build than the queries
+// save. None is larger than n or MAX_BLOCK_SIZE, except that one is always
+// allowed.
+pub fn default_candidates(n: usize) -> Vec<usize> {
+    let max = (2 * default_block_size(n)).min(MAX_BLOCK_SIZE).min(n);
+
+    (1..=max.max(1)).collect()
+}
+
+fn measure(numbers: &[u64], block_size: usize, queries: &[(usize, usize)]) -> Candidate {
+    let start = Instant::now();
+    let rmq = RMQSpanningBlocks::with_block_size(numbers.to_vec(), block_size).unwrap();
+    let construction = start.elapsed();
+
+    let start = Instant::now();
+    for (from, to) in queries {
+        std::hint::black_box(rmq.range_minimum_query(*from, *to).unwrap());
+    }
+    let queries_time = start.elapsed();
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+
+    Candidate {
+        block_size,
+        construction,
+        queries: queries_time,
+        query_count: queries.len(),
+        space: rmq.size_of(&mut ops),
+    }
+}
+
+// Builds RMQSpanningBlocks over numbers with every candidate block size and
+// answers the same uniformly random queries with each. Returns the block
+// size with the lowest cost under objective, the smaller one on ties, and
+// the measurements of all candidates.
+//
+// Times are single measurements, so for close candidates the pick can
+// change from run to run.
+pub fn tune_block_size(
+    numbers: &[u64],
+    candidates: &[usize],
+    queries: usize,
+    seed: u64,
+    objective: Objective,
+) -> Result<(usize, Vec<Candidate>), RMQError> {
+    let n = numbers.len();
+    if n == 0 {
+        return Err(RMQError::OutOfRange);
+    }
+
+    let candidates = match candidates {
+        [] => default_candidates(n),
+        candidates => candidates.to_vec(),
+    };
+    for block_size in &candidates {
+        super::check_block_size(*block_size)?;
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let queries: Vec<(usize, usize)> = (0..queries)
+        .map(|_| {
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n);
+            (a.min(b), a.max(b))
+        })
+        .collect();
+
+    let measured: Vec<Candidate> = candidates
+        .iter()
+        .map(|block_size| {
+            let candidate = measure(numbers, *block_size, &queries);
+            debug!("{}", candidate);
+            candidate
+        })
+        .collect();
+
+    let best = measured
+        .iter()
+        .min_by_key(|candidate| (candidate.cost(objective), candidate.block_size))
+        .unwrap()
+        .block_size;
+
+    info!(
+        "Best block size for {:?}: {} (default {})",
+        objective,
+        best,
+        default_block_size(n)
+    );
+
+    Ok((best, measured))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rmq::differential;
+
+    #[test]
+    fn testing_block_size() {
+        let numbers: Vec<u64> = (0..500).map(|i| (i * 7919) % 101).collect();
+
+        for block_size in [1, 2, 5, 31] {
+            let rmq = RMQSpanningBlocks::with_block_size(numbers.clone(), block_size).unwrap();
+            assert_eq!(block_size, rmq.block_size());
+
+            for (from, to) in [(0, 499), (3, 3), (17, 260), (480, 499)] {
+                let got = rmq.range_minimum_query(from, to).unwrap();
+                assert_eq!(numbers[from..=to].iter().min(), Some(&numbers[got]));
+            }
+        }
+
+        assert_eq!(
+            Some(RMQError::InvalidBlockSize {
+                block_size: 0,
+                max: MAX_BLOCK_SIZE
+            }),
+            RMQSpanningBlocks::with_block_size(numbers.clone(), 0).err()
+        );
+        assert!(RMQSpanningBlocks::with_block_size(numbers.clone(), 32).is_err());
+
+        let options = differential::DifferentialOptions {
+            cases: 100,
+            max_size: 100,
+            queries: 20,
+            seed: 2,
+        };
+        for block_size in [1, 2, 3, 7, 31] {
+            let failure = differential::run(
+                "spanning-blocks",
+                &move |numbers| {
+                    Box::new(RMQSpanningBlocks::with_block_size(numbers, block_size).unwrap())
+                },
+                &options,
+            );
+            assert!(failure.is_none(), "{}", failure.unwrap());
+        }
+        assert_eq!(
+            default_block_size(500),
+            RMQSpanningBlocks::new(numbers).block_size()
+        );
+    }
+
+    #[test]
+    fn testing_tune_block_size() {
+        let numbers: Vec<u64> = (0..2000).map(|i| (i * 7919) % 1009).collect();
+
+        // Default block size 3.
+        let (best, measured) = tune_block_size(&numbers, &[], 200, 1, Objective::Total).unwrap();
+        assert_eq!(6, measured.len());
+        assert!((1..=6).contains(&best));
+
+        // Space is deterministic: the sparse table over the blocks dominates
+        // small block sizes.
+        let (best, measured) =
+            tune_block_size(&numbers, &[1, 4, 8], 10, 1, Objective::Space).unwrap();
+        assert_eq!(3, measured.len());
+        assert_ne!(1, best);
+        assert!(measured.iter().all(|candidate| candidate.query_count == 10));
+
+        assert_eq!(
+            Some(RMQError::InvalidBlockSize {
+                block_size: 40,
+                max: MAX_BLOCK_SIZE
+            }),
+            tune_block_size(&numbers, &[4, 40], 10, 1, Objective::Query).err()
+        );
+        assert_eq!(
+            Some(RMQError::OutOfRange),
+            tune_block_size(&[], &[], 10, 1, Objective::Query).err()
+        );
+
+        assert_eq!(vec![1], default_candidates(1));
+        assert_eq!(vec![1, 2], default_candidates(3));
+        assert_eq!(vec![1, 2], default_candidates(16));
+        assert_eq!(MAX_BLOCK_SIZE, default_candidates(usize::MAX).len());
+    }
+}
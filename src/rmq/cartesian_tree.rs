@@ -0,0 +1,340 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::instances::{self, InstanceError};
+
+// Marks a missing parent or child.
+const NONE: usize = usize::MAX;
+
+// Cartesian tree of an array: the root is the position of the minimum, the
+// left and right subtrees are the Cartesian trees left and right of it.
+// Nodes are the positions of the array, so an in-order traversal is just
+// 0..n.
+//
+// Ties go to the left: of equal numbers the leftmost is the ancestor. The
+// root of every subtree is then the position RMQNaiveSlow answers for the
+// subtree's range, and two arrays have the same shape exactly if every
+// range minimum query gives the same position on both.
+#[derive(MallocSizeOf, Clone, Debug, PartialEq)]
+pub struct CartesianTree {
+    root: usize,
+    parents: Vec<usize>,
+    lefts: Vec<usize>,
+    rights: Vec<usize>,
+    // Subtree of i covers the positions starts[i]..=ends[i].
+    starts: Vec<usize>,
+    ends: Vec<usize>,
+    // Push (true) and pop (false) of every number while building, the pops
+    // at the end included. Two bits per number.
+    shape: Vec<bool>,
+}
+
+fn optional(node: usize) -> Option<usize> {
+    match node {
+        NONE => None,
+        node => Some(node),
+    }
+}
+
+impl CartesianTree {
+    // Linear time: the rightmost path of the tree is kept on a stack, every
+    // new number pops the greater ones and hangs them as its left subtree.
+    pub fn new(numbers: &[u64]) -> Self {
+        let n = numbers.len();
+
+        let mut parents = vec![NONE; n];
+        let mut lefts = vec![NONE; n];
+        let mut rights = vec![NONE; n];
+        let mut starts = vec![0usize; n];
+        let mut ends = vec![n.saturating_sub(1); n];
+        let mut shape = Vec::with_capacity(2 * n);
+
+        let mut stack: Vec<usize> = Vec::new();
+
+        for (i, number) in numbers.iter().enumerate() {
+            let mut last = NONE;
+
+            while let Some(top) = stack.last() {
+                if numbers[*top] <= *number {
+                    break;
+                }
+
+                last = stack.pop().unwrap();
+                ends[last] = i - 1;
+                shape.push(false);
+            }
+
+            lefts[i] = last;
+            starts[i] = i;
+            if last != NONE {
+                parents[last] = i;
+                starts[i] = starts[last];
+            }
+
+            if let Some(top) = stack.last() {
+                rights[*top] = i;
+                parents[i] = *top;
+            }
+
+            stack.push(i);
+            shape.push(true);
+        }
+
+        shape.extend(std::iter::repeat_n(false, stack.len()));
+
+        Self {
+            root: stack.first().copied().unwrap_or(NONE),
+            parents,
+            lefts,
+            rights,
+            starts,
+            ends,
+            shape,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    // None for the tree of an empty array.
+    pub fn root(&self) -> Option<usize> {
+        optional(self.root)
+    }
+
+    #[allow(dead_code)]
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        optional(self.parents[node])
+    }
+
+    pub fn left(&self, node: usize) -> Option<usize> {
+        optional(self.lefts[node])
+    }
+
+    pub fn right(&self, node: usize) -> Option<usize> {
+        optional(self.rights[node])
+    }
+
+    // Positions covered by the subtree of node, inclusive. The node is the
+    // leftmost minimum of them.
+    #[allow(dead_code)]
+    pub fn subtree(&self, node: usize) -> (usize, usize) {
+        (self.starts[node], self.ends[node])
+    }
+
+    // Canonical shape code: true for every push, false for every pop while
+    // building, 2n bits. Equal codes mean equal shapes.
+    #[allow(dead_code)]
+    pub fn shape_code(&self) -> &[bool] {
+        &self.shape
+    }
+
+    // The shape code as a number, first bit highest, if it fits into 64
+    // bits. Unlike the Catalan index RMQSpanningBlocks uses, most numbers
+    // below 2^2n are the code of no shape.
+    #[allow(dead_code)]
+    pub fn shape_number(&self) -> Option<u64> {
+        if self.shape.len() > 64 {
+            return None;
+        }
+
+        Some(
+            self.shape
+                .iter()
+                .fold(0u64, |number, bit| (number << 1) | *bit as u64),
+        )
+    }
+
+    // Node before its left and then its right subtree.
+    #[allow(dead_code)]
+    pub fn preorder(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.len());
+        let mut stack: Vec<usize> = self.root().into_iter().collect();
+
+        while let Some(node) = stack.pop() {
+            order.push(node);
+
+            // Right first, so the left subtree comes out first.
+            stack.extend(self.right(node));
+            stack.extend(self.left(node));
+        }
+
+        order
+    }
+
+    // Both subtrees before the node.
+    #[allow(dead_code)]
+    pub fn postorder(&self) -> Vec<usize> {
+        // Reversed preorder with the children swapped.
+        let mut order = Vec::with_capacity(self.len());
+        let mut stack: Vec<usize> = self.root().into_iter().collect();
+
+        while let Some(node) = stack.pop() {
+            order.push(node);
+
+            stack.extend(self.left(node));
+            stack.extend(self.right(node));
+        }
+
+        order.reverse();
+        order
+    }
+
+    // Graphviz export, nodes labeled with position and number.
+    pub fn to_dot(&self, numbers: &[u64]) -> String {
+        assert_eq!(self.len(), numbers.len(), "numbers of another tree");
+
+        let mut dot = String::from("digraph cartesian_tree {\n");
+
+        for (node, number) in numbers.iter().enumerate() {
+            writeln!(dot, "  {} [label=\"{}: {}\"];", node, node, number).unwrap();
+
+            for (child, side) in [(self.left(node), "L"), (self.right(node), "R")] {
+                if let Some(child) = child {
+                    writeln!(dot, "  {} -> {} [label=\"{}\"];", node, child, side).unwrap();
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Writes the Cartesian tree of the numbers of an RMQ instance to output in
+// the Graphviz format. The queries are not read.
+//
+// Returns the tree, e.g. to print its root.
+pub fn write_cartesian_tree(input: &Path, output: &Path) -> Result<CartesianTree, InstanceError> {
+    let numbers = instances::stream_rmq_instance(input)?.numbers;
+
+    let tree = CartesianTree::new(&numbers);
+
+    std::fs::write(output, tree.to_dot(&numbers)).map_err(|error| InstanceError::Io {
+        path: output.to_path_buf(),
+        error,
+    })?;
+
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::rmq::naive_slow::RMQNaiveSlow;
+
+    #[test]
+    fn testing_cartesian_tree() {
+        //     1
+        //    / \
+        //   0   6
+        //      /
+        //     2
+        //      \
+        //       3
+        //        \
+        //         5
+        //        /
+        //       4
+        let numbers = [5, 1, 3, 4, 8, 7, 2];
+        let tree = CartesianTree::new(&numbers);
+
+        assert_eq!(Some(1), tree.root());
+        assert_eq!(None, tree.parent(1));
+        assert_eq!(Some(0), tree.left(1));
+        assert_eq!(Some(6), tree.right(1));
+        assert_eq!(Some(2), tree.left(6));
+        assert_eq!(Some(3), tree.right(2));
+        assert_eq!(Some(5), tree.right(3));
+        assert_eq!(Some(4), tree.left(5));
+        assert_eq!(None, tree.left(2));
+        assert_eq!((2, 5), tree.subtree(2));
+        assert_eq!((0, 6), tree.subtree(1));
+
+        assert_eq!(vec![1, 0, 6, 2, 3, 5, 4], tree.preorder());
+        assert_eq!(vec![0, 4, 5, 3, 2, 6, 1], tree.postorder());
+
+        let dot = tree.to_dot(&numbers);
+        assert!(dot.starts_with("digraph cartesian_tree {\n"));
+        assert!(dot.contains("  1 [label=\"1: 1\"];\n"));
+        assert!(dot.contains("  1 -> 6 [label=\"R\"];\n"));
+
+        let empty = CartesianTree::new(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(None, empty.root());
+        assert!(empty.preorder().is_empty());
+        assert_eq!(Some(0), empty.shape_number());
+    }
+
+    #[test]
+    fn testing_write_cartesian_tree() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("rmq_project_cartesian_{}.txt", std::process::id()));
+        let output = dir.join(format!("rmq_project_cartesian_{}.dot", std::process::id()));
+
+        std::fs::write(&input, "4\n3\n1\n2\n0\n0,1\n").unwrap();
+        let tree = write_cartesian_tree(&input, &output).unwrap();
+
+        assert_eq!(Some(3), tree.root());
+        assert_eq!(
+            tree.to_dot(&[3, 1, 2, 0]),
+            std::fs::read_to_string(&output).unwrap()
+        );
+
+        std::fs::write(&input, "2\n3\n").unwrap();
+        assert!(matches!(
+            write_cartesian_tree(&input, &output),
+            Err(InstanceError::MissingNumbers { .. })
+        ));
+    }
+
+    #[test]
+    fn testing_cartesian_tree_random() {
+        let mut rng = StdRng::seed_from_u64(5);
+
+        for _ in 0..200 {
+            let n = rng.gen_range(1..40);
+            let max_value = rng.gen_range(0..10);
+            let numbers: Vec<u64> = (0..n).map(|_| rng.gen_range(0..=max_value)).collect();
+
+            let tree = CartesianTree::new(&numbers);
+            let naive = RMQNaiveSlow::new(numbers.clone());
+
+            assert_eq!(
+                naive.range_minimum_query(0, n - 1).ok(),
+                tree.root(),
+                "{:?}",
+                numbers
+            );
+
+            for node in 0..n {
+                let (start, end) = tree.subtree(node);
+
+                // Every subtree root is the leftmost minimum of its range.
+                assert_eq!(Ok(node), naive.range_minimum_query(start, end));
+
+                for child in [tree.left(node), tree.right(node)].into_iter().flatten() {
+                    assert_eq!(Some(node), tree.parent(child));
+                }
+            }
+
+            let mut preorder = tree.preorder();
+            preorder.sort_unstable();
+            assert_eq!((0..n).collect::<Vec<_>>(), preorder);
+            assert_eq!(2 * n, tree.shape_code().len());
+
+            // Same shape exactly if every query gives the same position.
+            let other: Vec<u64> = numbers.iter().map(|number| number * 3 + 1).collect();
+            assert_eq!(tree.shape_code(), CartesianTree::new(&other).shape_code());
+        }
+    }
+}
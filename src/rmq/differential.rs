@@ -0,0 +1,444 @@
+use std::cell::Cell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use clap::ValueEnum;
+use log::{debug, info};
+
+use super::naive_slow::RMQNaiveSlow;
+use super::{Algorithm, RMQ};
+
+// Differential testing: every algorithm answers random queries on random
+// numbers and is compared to RMQNaiveSlow. Ties may resolve to different
+// positions, so an answer is correct if it lies in the range and holds the
+// same value as the oracle's. Panics count as wrong answers.
+//
+// A failing case is shrunk, by dropping numbers and making them smaller, for
+// as long as it keeps failing.
+
+pub struct DifferentialOptions {
+    // Number of random arrays.
+    pub cases: usize,
+    // Largest array, sizes are uniform in 1..=max_size.
+    pub max_size: usize,
+    // Queries asked on every array.
+    pub queries: usize,
+    pub seed: u64,
+}
+
+// One query on one array.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub numbers: Vec<u64>,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Answer {
+    Position(usize),
+    Error(String),
+    Panic(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub name: String,
+    pub case: Case,
+    pub want: usize,
+    pub got: Answer,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let numbers = &self.case.numbers;
+
+        write!(
+            f,
+            "{}: query {},{} on {:?}: want {} (value {}) got ",
+            self.name, self.case.from, self.case.to, numbers, self.want, numbers[self.want]
+        )?;
+
+        match &self.got {
+            Answer::Position(got) if *got < numbers.len() => {
+                write!(f, "{} (value {})", got, numbers[*got])
+            }
+            Answer::Position(got) => write!(f, "{} (out of bounds)", got),
+            Answer::Error(error) => write!(f, "error: {}", error),
+            Answer::Panic(message) => write!(f, "panic: {}", message),
+        }
+    }
+}
+
+type Build = dyn Fn(Vec<u64>) -> Box<dyn RMQ>;
+
+// Upper bound on the cases tried while shrinking one failure.
+const SHRINK_ATTEMPTS: usize = 10_000;
+
+thread_local! {
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+// Panics of the algorithms under test are expected, their messages are
+// returned as answers instead of being printed. Only the thread running
+// the harness is silenced.
+fn quiet_panics<T>(f: impl FnOnce() -> T) -> std::thread::Result<T> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !QUIET.with(|quiet| quiet.get()) {
+                previous(info);
+            }
+        }));
+    });
+
+    QUIET.with(|quiet| quiet.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    QUIET.with(|quiet| quiet.set(false));
+
+    result
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    "unknown panic".to_string()
+}
+
+fn is_correct(case: &Case, want: usize, got: &Answer) -> bool {
+    match got {
+        Answer::Position(got) => {
+            *got >= case.from && *got <= case.to && case.numbers[*got] == case.numbers[want]
+        }
+        _ => false,
+    }
+}
+
+fn answer(rmq: &dyn RMQ, from: usize, to: usize) -> Answer {
+    match quiet_panics(|| rmq.range_minimum_query(from, to)) {
+        Ok(Ok(position)) => Answer::Position(position),
+        Ok(Err(error)) => Answer::Error(error.to_string()),
+        Err(payload) => Answer::Panic(panic_message(payload)),
+    }
+}
+
+// Builds a fresh structure for the case and asks its query.
+pub fn check(name: &str, build: &Build, case: &Case) -> Option<Failure> {
+    let want = RMQNaiveSlow::new(case.numbers.clone())
+        .range_minimum_query(case.from, case.to)
+        .unwrap();
+
+    let got = match quiet_panics(|| build(case.numbers.clone())) {
+        Ok(rmq) => answer(rmq.as_ref(), case.from, case.to),
+        Err(payload) => Answer::Panic(panic_message(payload)),
+    };
+
+    if is_correct(case, want, &got) {
+        return None;
+    }
+
+    Some(Failure {
+        name: name.to_string(),
+        case: case.clone(),
+        want,
+        got,
+    })
+}
+
+// Smaller variants of the case, roughly the most promising first.
+fn candidates(case: &Case) -> Vec<Case> {
+    let n = case.numbers.len();
+    let mut candidates = Vec::new();
+
+    // Drop chunks of numbers, from halves down to single numbers. Numbers
+    // inside the range shrink it, but it must keep at least one number.
+    let mut chunk = n / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start + chunk <= n {
+            let end = start + chunk;
+            let inside = end.min(case.to + 1).saturating_sub(start.max(case.from));
+
+            if inside <= case.to - case.from {
+                let before = end.min(case.from).saturating_sub(start);
+
+                let mut numbers = case.numbers[..start].to_vec();
+                numbers.extend_from_slice(&case.numbers[end..]);
+
+                candidates.push(Case {
+                    numbers,
+                    from: case.from - before,
+                    to: case.to - before - inside,
+                });
+            }
+
+            start += chunk;
+        }
+
+        chunk /= 2;
+    }
+
+    // Replace every number by its rank among the distinct numbers.
+    let mut distinct = case.numbers.clone();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let ranks: Vec<u64> = case
+        .numbers
+        .iter()
+        .map(|number| distinct.binary_search(number).unwrap() as u64)
+        .collect();
+    if ranks != case.numbers {
+        candidates.push(Case {
+            numbers: ranks,
+            ..case.clone()
+        });
+    }
+
+    // Make single numbers smaller.
+    for i in 0..n {
+        let number = case.numbers[i];
+
+        for smaller in [0, number / 2, number.saturating_sub(1)] {
+            if smaller < number {
+                let mut numbers = case.numbers.clone();
+                numbers[i] = smaller;
+
+                candidates.push(Case {
+                    numbers,
+                    ..case.clone()
+                });
+            }
+        }
+    }
+
+    candidates
+}
+
+// Greedily takes the first smaller case that still fails, until none does.
+pub fn shrink(build: &Build, failure: Failure) -> Failure {
+    let mut failure = failure;
+    let mut attempts = 0;
+
+    'shrinking: loop {
+        for candidate in candidates(&failure.case) {
+            if attempts == SHRINK_ATTEMPTS {
+                break 'shrinking;
+            }
+            attempts += 1;
+
+            if let Some(smaller) = check(&failure.name, build, &candidate) {
+                debug!(
+                    "Shrunk to {} numbers, query {},{}",
+                    smaller.case.numbers.len(),
+                    smaller.case.from,
+                    smaller.case.to
+                );
+
+                failure = smaller;
+                continue 'shrinking;
+            }
+        }
+
+        break;
+    }
+
+    failure
+}
+
+fn random_numbers(rng: &mut StdRng, max_size: usize) -> Vec<u64> {
+    let n = rng.gen_range(1..=max_size);
+
+    // Small universes give many duplicates, large ones almost none.
+    let max_value = match rng.gen_range(0..4) {
+        0 => 1,
+        1 => 16,
+        2 => n as u64,
+        _ => u64::MAX,
+    };
+
+    (0..n).map(|_| rng.gen_range(0..=max_value)).collect()
+}
+
+// Runs one implementation on random cases. Returns the first failure,
+// shrunk.
+pub fn run(name: &str, build: &Build, options: &DifferentialOptions) -> Option<Failure> {
+    assert!(options.max_size > 0, "arrays need at least one number");
+
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    for i in 0..options.cases {
+        let numbers = random_numbers(&mut rng, options.max_size);
+        let n = numbers.len();
+
+        let oracle = RMQNaiveSlow::new(numbers.clone());
+        let rmq = quiet_panics(|| build(numbers.clone())).ok();
+
+        for _ in 0..options.queries {
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n);
+            let case = Case {
+                numbers: numbers.clone(),
+                from: a.min(b),
+                to: a.max(b),
+            };
+
+            let correct = match &rmq {
+                Some(rmq) => {
+                    let want = oracle.range_minimum_query(case.from, case.to).unwrap();
+                    is_correct(&case, want, &answer(rmq.as_ref(), case.from, case.to))
+                }
+                None => false,
+            };
+
+            if !correct {
+                info!("{}: case {} failed, shrinking", name, i);
+
+                let failure = check(name, build, &case).unwrap();
+                return Some(shrink(build, failure));
+            }
+        }
+    }
+
+    None
+}
+
+// Every algorithm except the oracle itself.
+pub fn algorithms() -> Vec<Algorithm> {
+    Algorithm::value_variants()
+        .iter()
+        .copied()
+        .filter(|algorithm| *algorithm != Algorithm::NaiveSlow)
+        .collect()
+}
+
+// Runs all given algorithms, returns one shrunk failure for each failing
+// one.
+pub fn run_algorithms(algorithms: &[Algorithm], options: &DifferentialOptions) -> Vec<Failure> {
+    algorithms
+        .iter()
+        .filter_map(|algorithm| {
+            let algorithm = *algorithm;
+            run(
+                &format!("{:?}", algorithm),
+                &move |numbers| algorithm.build(numbers),
+                options,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rmq::RMQError;
+
+    // Always answers the right end of the range.
+    #[derive(MallocSizeOf)]
+    struct RightEnd;
+
+    impl RMQ for RightEnd {
+        fn new(_numbers: Vec<u64>) -> Self {
+            RightEnd
+        }
+
+        fn range_minimum_query(&self, _from: usize, to: usize) -> Result<usize, RMQError> {
+            Ok(to)
+        }
+
+        fn numbers(&self) -> &[u64] {
+            &[]
+        }
+    }
+
+    // Panics on arrays with the number 7 somewhere.
+    #[derive(MallocSizeOf)]
+    struct NoSevens;
+
+    impl RMQ for NoSevens {
+        fn new(numbers: Vec<u64>) -> Self {
+            assert!(!numbers.contains(&7), "found a seven");
+            NoSevens
+        }
+
+        fn range_minimum_query(&self, from: usize, _to: usize) -> Result<usize, RMQError> {
+            Ok(from)
+        }
+
+        fn numbers(&self) -> &[u64] {
+            &[]
+        }
+    }
+
+    #[test]
+    fn testing_differential_all_algorithms() {
+        let options = DifferentialOptions {
+            cases: 300,
+            max_size: 100,
+            queries: 50,
+            seed: 1,
+        };
+
+        let failures = run_algorithms(&algorithms(), &options);
+
+        for failure in &failures {
+            println!("{}", failure);
+        }
+        assert!(failures.is_empty());
+    }
+
+    #[test]
+    fn testing_differential_shrinks() {
+        let options = DifferentialOptions {
+            cases: 10,
+            max_size: 50,
+            queries: 10,
+            seed: 3,
+        };
+
+        let failure = run(
+            "right-end",
+            &|numbers| Box::new(RightEnd::new(numbers)),
+            &options,
+        )
+        .expect("right-end must fail");
+
+        // The smallest failing case: the minimum is left of a larger number.
+        assert_eq!(
+            Case {
+                numbers: vec![0, 1],
+                from: 0,
+                to: 1
+            },
+            failure.case
+        );
+        assert_eq!(0, failure.want);
+        assert_eq!(Answer::Position(1), failure.got);
+
+        let seven = Case {
+            numbers: vec![3, 9, 7, 12, 1, 7, 5],
+            from: 1,
+            to: 5,
+        };
+        let failure = check(
+            "no-sevens",
+            &|numbers| Box::new(NoSevens::new(numbers)),
+            &seven,
+        )
+        .expect("no-sevens must fail");
+        let failure = shrink(&|numbers| Box::new(NoSevens::new(numbers)), failure);
+
+        assert_eq!(vec![7], failure.case.numbers);
+        assert!(matches!(failure.got, Answer::Panic(ref message) if message == "found a seven"));
+        assert!(failure.to_string().contains("panic: found a seven"));
+    }
+}
@@ -1,11 +1,21 @@
+mod block_size;
+mod cartesian_tree;
+mod differential;
 mod naive_fast;
 mod naive_slow;
+mod range_query;
+mod rmq_2d;
 mod rmq_spanning_blocks;
 mod rmq_sparse;
+mod rmq_sparse_compact;
+mod smaller_values;
+mod top_k;
 
 use std::error::Error;
 use std::fmt;
 
+use crate::instances::InstanceError;
+#[cfg(test)]
 use crate::instances::RMQInstance;
 
 use super::instances;
@@ -18,58 +28,377 @@ use super::report;
 
 use crate::malloc_size_of::MallocSizeOf;
 use crate::malloc_size_of::MallocSizeOfOps;
+use log::debug;
+#[cfg(test)]
+use log::trace;
 
-use super::debug::DEBUG;
+pub use block_size::{tune_block_size, Objective};
+pub use cartesian_tree::write_cartesian_tree;
+pub use differential::{algorithms, run_algorithms, DifferentialOptions};
+pub use range_query::Operation;
+pub use rmq_spanning_blocks::check_block_size;
 
 #[derive(Debug, PartialEq)]
 pub enum RMQError {
     OutOfRange,
+    // Block size of RMQSpanningBlocks outside of 1..=max.
+    InvalidBlockSize {
+        block_size: usize,
+        max: usize,
+    },
+    // Numbers of a matrix that are not rows * cols many.
+    InvalidMatrix {
+        rows: usize,
+        cols: usize,
+        len: usize,
+    },
 }
 
 impl fmt::Display for RMQError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             RMQError::OutOfRange => f.write_str("Out of range"),
+            RMQError::InvalidBlockSize { block_size, max } => {
+                write!(f, "Block size {} not between 1 and {}", block_size, max)
+            }
+            RMQError::InvalidMatrix { rows, cols, len } => {
+                write!(f, "{} numbers are not a {}x{} matrix", len, rows, cols)
+            }
         }
     }
 }
 
 impl Error for RMQError {}
 
-pub fn rmq(path: &Path, out: Option<String>) {
-    if DEBUG {
-        println!("rmq");
+// A benchmark or check fails on reading the instance or on building the
+// structure from its numbers, e.g. with an invalid block size.
+#[derive(Debug)]
+pub enum BenchmarkError {
+    Instance(InstanceError),
+    Build(RMQError),
+}
+
+impl fmt::Display for BenchmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BenchmarkError::Instance(error) => error.fmt(f),
+            BenchmarkError::Build(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for BenchmarkError {}
+
+impl From<InstanceError> for BenchmarkError {
+    fn from(error: InstanceError) -> Self {
+        BenchmarkError::Instance(error)
     }
+}
 
-    //let path = Path::new("testdata/rmq_examples/rmq_example_1.txt");
+impl From<RMQError> for BenchmarkError {
+    fn from(error: RMQError) -> Self {
+        BenchmarkError::Build(error)
+    }
+}
 
-    benchmark_and_check_path::<rmq_spanning_blocks::RMQSpanningBlocks>(path, None, out);
-    // benchmark_and_check_path::<rmq_sparse::RMQSparse>(path, None, None);
-    // benchmark_and_check_path::<naive_slow::RMQNaiveSlow>(path, None, None);
+// All RMQ implementations, to pick one at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Algorithm {
+    NaiveSlow,
+    NaiveFast,
+    Sparse,
+    SparseCompact,
+    SpanningBlocks,
 }
 
-pub trait RMQ {
-    fn new(numbers: Vec<u64>) -> Self;
+impl Algorithm {
+    // Value of --algo, also the name in the benchmark results.
+    pub fn name(self) -> String {
+        clap::ValueEnum::to_possible_value(&self)
+            .unwrap()
+            .get_name()
+            .to_string()
+    }
+
+    pub fn build(self, numbers: Vec<u64>) -> Box<dyn RMQ> {
+        match self {
+            Algorithm::NaiveSlow => Box::new(naive_slow::RMQNaiveSlow::new(numbers)),
+            Algorithm::NaiveFast => Box::new(naive_fast::RMQNaiveFast::new(numbers)),
+            Algorithm::Sparse => Box::new(rmq_sparse::RMQSparse::new(numbers)),
+            Algorithm::SparseCompact => {
+                Box::new(rmq_sparse_compact::RMQSparseCompact::new(numbers))
+            }
+            Algorithm::SpanningBlocks => {
+                Box::new(rmq_spanning_blocks::RMQSpanningBlocks::new(numbers))
+            }
+        }
+    }
+
+    pub fn benchmark(self, path: &Path, out: Option<report::Output>) -> Result<(), InstanceError> {
+        match self {
+            Algorithm::NaiveSlow => {
+                benchmark_streaming_path::<naive_slow::RMQNaiveSlow>(self, path, out)
+            }
+            Algorithm::NaiveFast => {
+                benchmark_streaming_path::<naive_fast::RMQNaiveFast>(self, path, out)
+            }
+            Algorithm::Sparse => benchmark_streaming_path::<rmq_sparse::RMQSparse>(self, path, out),
+            Algorithm::SparseCompact => {
+                benchmark_streaming_path::<rmq_sparse_compact::RMQSparseCompact>(self, path, out)
+            }
+            Algorithm::SpanningBlocks => {
+                benchmark_streaming_path::<rmq_spanning_blocks::RMQSpanningBlocks>(self, path, out)
+            }
+        }
+    }
+}
+
+// MallocSizeOf lets the space of a Box<dyn RMQ> be measured.
+pub trait RMQ: MallocSizeOf {
+    fn new(numbers: Vec<u64>) -> Self
+    where
+        Self: Sized;
     fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError>;
+    // What the structure was built from.
+    fn numbers(&self) -> &[u64];
+
+    // See top_k.rs.
+    fn top_k(&self, from: usize, to: usize, k: usize) -> Result<Vec<(usize, u64)>, RMQError> {
+        top_k::top_k(self, from, to, k)
+    }
+
+    fn all_minimum_positions(&self, from: usize, to: usize) -> Result<Vec<usize>, RMQError> {
+        top_k::all_minimum_positions(self, from, to)
+    }
+
+    // Nearest position left of i with a smaller number, see
+    // smaller_values.rs.
+    fn previous_smaller(&self, i: usize) -> Result<Option<usize>, RMQError> {
+        smaller_values::previous_smaller(self, i)
+    }
+
+    fn next_smaller(&self, i: usize) -> Result<Option<usize>, RMQError> {
+        smaller_values::next_smaller(self, i)
+    }
+
+    // Like next_smaller, but only at most d positions after i.
+    fn next_smaller_within(&self, i: usize, d: usize) -> Result<Option<usize>, RMQError> {
+        smaller_values::next_smaller_within(self, i, d)
+    }
+}
+
+// Next to RMQ: answers a range with the combination of its numbers under an
+// associative operation instead of the position of the minimum. See
+// range_query.rs.
+pub trait RangeQuery: MallocSizeOf {
+    #[allow(dead_code)]
+    fn new(numbers: Vec<u64>) -> Self
+    where
+        Self: Sized;
+    fn range_query(&self, from: usize, to: usize) -> Result<u64, RMQError>;
+}
+
+// Benchmarks range queries under operation on the queries of an RMQ
+// instance.
+pub fn benchmark_range_query(
+    path: &Path,
+    operation: Operation,
+    disjoint: bool,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    range_query::benchmark(path, operation, disjoint, out)
+}
+
+pub fn check_range_query(
+    path: &Path,
+    operation: Operation,
+    disjoint: bool,
+) -> Result<u64, InstanceError> {
+    range_query::check_against_naive(path, operation, disjoint)
+}
+
+// Benchmarks the k smallest numbers of every query range, or with None all
+// positions of the minimum, with algorithm. See top_k.rs.
+pub fn benchmark_top_k(
+    path: &Path,
+    algorithm: Algorithm,
+    k: Option<usize>,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    top_k::benchmark(path, algorithm, k, out)
 }
 
+// Benchmarks previous and next smaller values of every position, the next
+// one at most within positions ahead, with algorithm. See smaller_values.rs.
+pub fn benchmark_smaller_values(
+    path: &Path,
+    algorithm: Algorithm,
+    within: Option<usize>,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    smaller_values::benchmark(path, algorithm, within, out)
+}
+
+pub fn check_smaller_values(
+    path: &Path,
+    algorithm: Algorithm,
+    within: Option<usize>,
+) -> Result<u64, InstanceError> {
+    smaller_values::check_against_precomputed(path, algorithm, within)
+}
+
+// Benchmarks two-dimensional range minimum queries on a matrix instance,
+// with algorithm over the columns. See rmq_2d.rs.
+pub fn benchmark_2d(
+    path: &Path,
+    algorithm: Algorithm,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    rmq_2d::benchmark(path, algorithm, out)
+}
+
+pub fn check_2d(path: &Path, algorithm: Algorithm) -> Result<u64, InstanceError> {
+    rmq_2d::check_against_naive(path, algorithm)
+}
+
+// Answers every query of the instance with the structure from build and with
+// oracle.
+//
+// Ties are allowed to resolve to different positions, so only the values
+// at the answers are compared. Returns the number of mismatches, the first
+// few are printed.
+pub fn check_against<E: From<InstanceError>>(
+    path: &Path,
+    build: impl FnOnce(Vec<u64>) -> Result<Box<dyn RMQ>, E>,
+    oracle: Algorithm,
+) -> Result<u64, E> {
+    let instance = instances::stream_rmq_instance(path)?;
+
+    let rmq = build(instance.numbers.clone())?;
+    let checker = oracle.build(instance.numbers.clone());
+    let numbers = instance.numbers;
+
+    let mut mismatches = 0;
+
+    for (i, query) in instance.queries.enumerate() {
+        let (from, to) = query?;
+
+        let want = checker.range_minimum_query(from, to).unwrap();
+        let got = rmq.range_minimum_query(from, to);
+
+        let correct = match got {
+            Ok(got) => got >= from && got <= to && numbers[got] == numbers[want],
+            Err(_) => false,
+        };
+
+        if !correct {
+            if mismatches < 10 {
+                eprintln!(
+                    "Mismatch in query nr {}: {},{} want: {} (value {}) got: {:?}",
+                    i, from, to, want, numbers[want], got
+                );
+            }
+            mismatches += 1;
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
 pub fn benchmark_and_check_path<T: RMQ + MallocSizeOf>(
     path: &Path,
     want: Option<Vec<usize>>,
-    out: Option<String>,
-) {
-    let instance = instances::read_rmq_instance(path).unwrap();
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    let instance = instances::read_rmq_instance(path)?;
     benchmark_and_check_instance::<T>(instance, want, out);
+
+    Ok(())
+}
+
+// Like benchmark, but reads the queries one at a time while answering them
+// and writes every answer right away, so the queries are never held in
+// memory. Results are reported under the name of algorithm, which has to
+// be the one of T.
+pub fn benchmark_streaming_path<T: RMQ + MallocSizeOf>(
+    algorithm: Algorithm,
+    path: &Path,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    benchmark_streaming_with(algorithm.name(), path, out, |numbers| {
+        Ok::<_, InstanceError>(T::new(numbers))
+    })
+}
+
+// RMQSpanningBlocks with the given block size instead of the default.
+pub fn spanning_blocks(numbers: Vec<u64>, block_size: usize) -> Result<Box<dyn RMQ>, RMQError> {
+    Ok(Box::new(
+        rmq_spanning_blocks::RMQSpanningBlocks::with_block_size(numbers, block_size)?,
+    ))
+}
+
+// Benchmarks RMQSpanningBlocks with the given block size.
+pub fn benchmark_spanning_blocks(
+    path: &Path,
+    block_size: usize,
+    out: Option<report::Output>,
+) -> Result<(), BenchmarkError> {
+    benchmark_streaming_with(Algorithm::SpanningBlocks.name(), path, out, |numbers| {
+        rmq_spanning_blocks::RMQSpanningBlocks::with_block_size(numbers, block_size)
+            .map_err(BenchmarkError::Build)
+    })
+}
+
+// Like benchmark_streaming_path, with build constructing the structure from
+// the numbers and results reported as algo.
+fn benchmark_streaming_with<T: RMQ, E: From<InstanceError>>(
+    algo: String,
+    path: &Path,
+    out: Option<report::Output>,
+    build: impl FnOnce(Vec<u64>) -> Result<T, E>,
+) -> Result<(), E> {
+    let instance = instances::stream_rmq_instance(path)?;
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    let rmq = build(instance.numbers)?;
+
+    let mut measurement = report::Measurement::new(algo, start.elapsed());
+
+    for (i, query) in instance.queries.enumerate() {
+        let (from, to) = query?;
+
+        let start = Instant::now();
+        let got = rmq.range_minimum_query(from, to).unwrap();
+        measurement.record_query(start.elapsed());
+
+        writer.push(got as u64);
+
+        if i % 100 == 0 {
+            debug!("Query nr {}", i);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = rmq.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
 }
 
+#[cfg(test)]
 pub fn benchmark_and_check_instance<T: RMQ + MallocSizeOf>(
     instance: RMQInstance,
     want: Option<Vec<usize>>,
-    out: Option<String>,
+    out: Option<report::Output>,
 ) {
-    if DEBUG {
-        println!("rmq");
-    }
+    trace!("rmq");
 
     // Check correctness.
     if let Some(want) = want {
@@ -77,9 +406,8 @@ pub fn benchmark_and_check_instance<T: RMQ + MallocSizeOf>(
         let rmq = T::new(numbers.clone());
 
         for (i, query) in instance.queries.clone().iter().enumerate() {
-            if DEBUG {
-                println!("Query nr {}: {:?}", i, query);
-            }
+            trace!("Query nr {}: {:?}", i, query);
+
             let got = rmq
                 .range_minimum_query(query.0 as usize, query.1 as usize)
                 .unwrap();
@@ -114,16 +442,38 @@ pub fn benchmark_and_check_instance<T: RMQ + MallocSizeOf>(
     benchmark::<T>(instance, out);
 }
 
-#[allow(dead_code)]
+// Asserts that got is a minimum of the query just like want. Ties may
+// resolve to different positions.
+#[cfg(test)]
+fn assert_same_minimum(numbers: &[u64], i: usize, query: (usize, usize), want: usize, got: usize) {
+    assert!(want < numbers.len());
+    assert!(want <= query.1, "Query: {:?}, want: {}", query, want);
+    assert!(want >= query.0, "Query: {:?}, want: {}", query, want);
+    assert!(
+        got < numbers.len(),
+        "Query: {:?} got: {} numbers.len: {}",
+        query,
+        got,
+        numbers.len()
+    );
+    assert!(got <= query.1, "Query: {:?}, got: {}", query, got);
+    assert!(got >= query.0, "Query: {:?}, got: {}", query, got);
+
+    assert_eq!(
+        numbers[want], numbers[got],
+        "Query check nr {}: {:?} want: {} got: {}",
+        i, query, want, got
+    );
+}
+
+#[cfg(test)]
 pub fn benchmark_and_check_with_checker<T: RMQ + MallocSizeOf, Checker: RMQ + MallocSizeOf>(
     path: &Path,
     want_number_checked: isize,
-) {
-    if DEBUG {
-        println!("rmq");
-    }
+) -> Result<(), InstanceError> {
+    trace!("rmq");
 
-    let instance = instances::read_rmq_instance(path).unwrap();
+    let instance = instances::read_rmq_instance(path)?;
 
     // Create want-vector to check against from checker.
     let checker: Checker = Checker::new(instance.numbers.clone());
@@ -139,26 +489,36 @@ pub fn benchmark_and_check_with_checker<T: RMQ + MallocSizeOf, Checker: RMQ + Ma
             .range_minimum_query(query.0 as usize, query.1 as usize)
             .unwrap();
 
-        if DEBUG {
-            println!("Query nr {}: {:?} -> {}", i, query, got);
-        }
+        trace!("Query nr {}: {:?} -> {}", i, query, got);
 
         want.push(got);
     }
+
+    let under_test: T = T::new(instance.numbers.clone());
+
+    for (i, (query, want)) in instance.queries.iter().zip(want).enumerate() {
+        let got = under_test.range_minimum_query(query.0, query.1).unwrap();
+
+        assert_same_minimum(&instance.numbers, i, *query, want, got);
+    }
+
+    benchmark::<T>(instance, None);
+
+    Ok(())
 }
 
-#[allow(dead_code)]
+#[cfg(test)]
 pub fn benchmark_and_check_with_checker_parallel<
     T: RMQ + MallocSizeOf,
     Checker: RMQ + MallocSizeOf,
 >(
     path: &Path,
     want_number_checked: isize,
-    out: Option<String>,
-) {
-    println!("rmq");
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    log::info!("rmq");
 
-    let instance = instances::read_rmq_instance(path).unwrap();
+    let instance = instances::read_rmq_instance(path)?;
 
     let numbers = instance.numbers.clone();
 
@@ -177,46 +537,24 @@ pub fn benchmark_and_check_with_checker_parallel<
             .range_minimum_query(query.0 as usize, query.1 as usize)
             .unwrap();
 
-        if DEBUG {
-            println!("Query nr {}: {:?}", i, query);
-        }
+        trace!("Query nr {}: {:?}", i, query);
+
         let got = under_test
             .range_minimum_query(query.0 as usize, query.1 as usize)
             .unwrap();
 
-        // Only check as long as we have enough want-elements.
-        if want_number_checked > 0 && i < want_number_checked as usize {
-            assert!(want < numbers.len());
-            assert!(want <= query.1, "Query: {:?}, want: {}", query, want);
-            assert!(want >= query.0, "Query: {:?}, want: {}", query, want);
-            assert!(
-                got < numbers.len(),
-                "Query: {:?} got: {} numbers.len: {}",
-                query,
-                got,
-                numbers.len()
-            );
-            assert!(got <= query.1, "Query: {:?}, got: {}", query, got);
-            assert!(got >= query.0, "Query: {:?}, got: {}", query, got);
-
-            assert_eq!(
-                want, got,
-                "Query check nr {}: {:?} want-resolved: {} got-resolved: {}",
-                i, query, numbers[want], numbers[got]
-            );
-        }
-
-        assert!(got < numbers.len());
+        assert_same_minimum(&numbers, i, *query, want, got);
 
-        if DEBUG {
-            println!("Query nr {}: {:?} -> {}", i, query, want);
-        }
+        trace!("Query nr {}: {:?} -> {}", i, query, want);
     }
 
     benchmark::<T>(instance, out);
+
+    Ok(())
 }
 
-fn benchmark<T: RMQ + MallocSizeOf>(instance: RMQInstance, out: Option<String>) {
+#[cfg(test)]
+fn benchmark<T: RMQ + MallocSizeOf>(instance: RMQInstance, out: Option<report::Output>) {
     // Clone numbers because we sort them.
     let numbers = instance.numbers;
 
@@ -226,29 +564,32 @@ fn benchmark<T: RMQ + MallocSizeOf>(instance: RMQInstance, out: Option<String>)
 
     let rmq = T::new(numbers);
 
+    // Only tests get here, the type name tells the structures apart.
+    let algo = std::any::type_name::<T>().rsplit("::").next().unwrap();
+    let mut measurement = report::Measurement::new(algo, start.elapsed());
+
     let queries_count = instance.queries.len();
 
     for (i, query) in instance.queries.iter().enumerate() {
-        got_all.push(
-            rmq.range_minimum_query(query.0 as usize, query.1 as usize)
-                .unwrap() as u64,
-        );
-
-        if DEBUG {
-            if i % 100 == 0 {
-                println!("Query nr {}/{}", i, queries_count);
-            }
+        let start = Instant::now();
+        let got = rmq
+            .range_minimum_query(query.0 as usize, query.1 as usize)
+            .unwrap();
+        measurement.record_query(start.elapsed());
+
+        got_all.push(got as u64);
+
+        if i % 100 == 0 {
+            debug!("Query nr {}/{}", i, queries_count);
         }
     }
 
-    let duration = start.elapsed();
-
     let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
-    let size = rmq.size_of(&mut ops);
+    measurement.space = rmq.size_of(&mut ops);
 
     report::write_out(out, got_all);
 
-    report::report("rmq", duration, size);
+    report::report(&measurement);
 }
 
 // #[test]
@@ -264,7 +605,7 @@ fn benchmark<T: RMQ + MallocSizeOf>(instance: RMQInstance, out: Option<String>)
 fn testing_rmq_naiveslow_benchmark1() {
     let path = Path::new("testdata/rmq_examples/rmq_example_1.txt");
 
-    benchmark_and_check_path::<naive_slow::RMQNaiveSlow>(path, None, None);
+    benchmark_and_check_path::<naive_slow::RMQNaiveSlow>(path, None, None).unwrap();
 }
 
 #[test]
@@ -272,7 +613,8 @@ fn testing_rmq_sparse_benchmark1() {
     let path = Path::new("testdata/rmq_examples/rmq_example_1.txt");
 
     // Only check first 2k because naive_slow is super slow.
-    benchmark_and_check_with_checker::<rmq_sparse::RMQSparse, naive_slow::RMQNaiveSlow>(path, 2000);
+    benchmark_and_check_with_checker::<rmq_sparse::RMQSparse, naive_slow::RMQNaiveSlow>(path, 2000)
+        .unwrap();
 }
 
 #[test]
@@ -282,7 +624,8 @@ fn testing_rmq_spanning_benchmark1() {
     benchmark_and_check_with_checker_parallel::<
         rmq_spanning_blocks::RMQSpanningBlocks,
         rmq_sparse::RMQSparse,
-    >(path, -1, None);
+    >(path, -1, None)
+    .unwrap();
 }
 
 #[test]
@@ -292,5 +635,32 @@ fn testing_rmq_spanning_benchmark2() {
     benchmark_and_check_with_checker_parallel::<
         rmq_spanning_blocks::RMQSpanningBlocks,
         rmq_sparse::RMQSparse,
-    >(path, -1, None);
+    >(path, -1, None)
+    .unwrap();
+}
+
+#[test]
+fn testing_rmq_spanning_block_size() {
+    let path = Path::new("testdata/rmq_examples/rmq_example_1.txt");
+
+    benchmark_spanning_blocks(path, 4, None).unwrap();
+    assert!(matches!(
+        benchmark_spanning_blocks(path, 40, None),
+        Err(BenchmarkError::Build(RMQError::InvalidBlockSize {
+            block_size: 40,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        benchmark_spanning_blocks(Path::new("testdata/does_not_exist.txt"), 4, None),
+        Err(BenchmarkError::Instance(InstanceError::Io { .. }))
+    ));
+
+    let build = |numbers| Ok::<_, BenchmarkError>(spanning_blocks(numbers, 4)?);
+    assert_eq!(0, check_against(path, build, Algorithm::Sparse).unwrap());
+    let build = |numbers| Ok::<_, BenchmarkError>(spanning_blocks(numbers, 0)?);
+    assert!(matches!(
+        check_against(path, build, Algorithm::Sparse),
+        Err(BenchmarkError::Build(_))
+    ));
 }
@@ -4,6 +4,7 @@ use super::RMQ;
 #[derive(MallocSizeOf, Clone)]
 pub struct RMQNaiveFast {
     naive: Vec<Vec<usize>>,
+    numbers: Vec<u64>,
 }
 
 impl RMQNaiveFast {
@@ -31,7 +32,7 @@ impl RMQNaiveFast {
             }
         }
 
-        return Self { naive: naive };
+        return Self { naive, numbers };
     }
 
     pub fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
@@ -51,4 +52,8 @@ impl RMQ for RMQNaiveFast {
     fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
         RMQNaiveFast::range_minimum_query(self, from, to)
     }
+
+    fn numbers(&self) -> &[u64] {
+        &self.numbers
+    }
 }
@@ -1,7 +1,6 @@
-use crate::debug::DEBUG;
-
 use super::RMQError;
 use super::RMQ;
+use log::trace;
 
 #[derive(MallocSizeOf, Clone)]
 pub struct RMQNaiveSlow {
@@ -14,12 +13,13 @@ impl RMQNaiveSlow {
     }
 
     pub fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
-        if from >= self.numbers.len() || to >= self.numbers.len() {
+        if from > to || to >= self.numbers.len() {
             return Err(RMQError::OutOfRange);
         }
 
-        let mut min_index = 0;
-        let mut min_value = std::u64::MAX;
+        // Start at from, so a range of only u64::MAX still has a minimum.
+        let mut min_index = from;
+        let mut min_value = self.numbers[from];
 
         for i in from..=to {
             if self.numbers[i] < min_value {
@@ -45,12 +45,13 @@ impl RMQNaiveSlow {
             to
         );
 
-        if DEBUG {
-            println!(
-                "RMQNaiveSlow::range_minimum_query({}, {}) = {} min_value={}",
-                from, to, min_index, min_value
-            );
-        }
+        trace!(
+            "RMQNaiveSlow::range_minimum_query({}, {}) = {} min_value={}",
+            from,
+            to,
+            min_index,
+            min_value
+        );
 
         Ok(min_index)
     }
@@ -64,4 +65,8 @@ impl RMQ for RMQNaiveSlow {
     fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
         RMQNaiveSlow::range_minimum_query(self, from, to)
     }
+
+    fn numbers(&self) -> &[u64] {
+        &self.numbers
+    }
 }
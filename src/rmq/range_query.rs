@@ -0,0 +1,434 @@
+use std::marker::PhantomData;
+use std::path::Path;
+use std::time::Instant;
+
+use super::heapsize;
+use super::instances;
+use super::report;
+use super::{RMQError, RangeQuery};
+
+use crate::instances::InstanceError;
+use crate::malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use log::{debug, trace};
+
+// Range queries answering with the combination of all numbers of a range
+// under an associative operation, instead of the position of the minimum.
+
+// Associative operation on numbers: combine(combine(a, b), c) equals
+// combine(a, combine(b, c)). Commutativity is not needed, ranges are always
+// combined from left to right.
+pub trait Semigroup {
+    fn combine(a: u64, b: u64) -> u64;
+}
+
+// combine(a, a) equals a, so overlapping parts of a range can be combined
+// twice. Allows the sparse table.
+pub trait Idempotent: Semigroup {}
+
+// Wrapping on overflow.
+#[derive(MallocSizeOf, Clone, Copy, Debug)]
+pub struct Sum;
+
+#[derive(MallocSizeOf, Clone, Copy, Debug)]
+pub struct Xor;
+
+// gcd(0, a) = a.
+#[derive(MallocSizeOf, Clone, Copy, Debug)]
+pub struct Gcd;
+
+#[derive(MallocSizeOf, Clone, Copy, Debug)]
+pub struct Max;
+
+#[derive(MallocSizeOf, Clone, Copy, Debug)]
+pub struct Min;
+
+impl Semigroup for Sum {
+    fn combine(a: u64, b: u64) -> u64 {
+        a.wrapping_add(b)
+    }
+}
+
+impl Semigroup for Xor {
+    fn combine(a: u64, b: u64) -> u64 {
+        a ^ b
+    }
+}
+
+impl Semigroup for Gcd {
+    fn combine(mut a: u64, mut b: u64) -> u64 {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    }
+}
+
+impl Semigroup for Max {
+    fn combine(a: u64, b: u64) -> u64 {
+        a.max(b)
+    }
+}
+
+impl Semigroup for Min {
+    fn combine(a: u64, b: u64) -> u64 {
+        a.min(b)
+    }
+}
+
+impl Idempotent for Gcd {}
+impl Idempotent for Max {}
+impl Idempotent for Min {}
+
+// Sparse table over combined values: the entry for [i, i + 2^j - 1] holds
+// their combination, a query combines the two overlapping power-of-two
+// ranges covering it. Only correct for idempotent operations.
+#[derive(MallocSizeOf, Clone)]
+pub struct SparseTable<S: Idempotent> {
+    // Level j has n - 2^j + 1 entries, level 0 are the numbers.
+    levels: Vec<Vec<u64>>,
+    operation: PhantomData<S>,
+}
+
+impl<S: Idempotent> SparseTable<S> {
+    pub fn new(numbers: Vec<u64>) -> Self {
+        let n = numbers.len();
+        let mut levels = vec![numbers];
+
+        let mut j = 1;
+        while 1 << j <= n {
+            let half = 1 << (j - 1);
+            let previous = &levels[j - 1];
+
+            let level = (0..=n - (1 << j))
+                .map(|i| S::combine(previous[i], previous[i + half]))
+                .collect();
+            levels.push(level);
+
+            j += 1;
+        }
+
+        Self {
+            levels,
+            operation: PhantomData,
+        }
+    }
+
+    pub fn range_query(&self, from: usize, to: usize) -> Result<u64, RMQError> {
+        if from > to || to >= self.levels[0].len() {
+            return Err(RMQError::OutOfRange);
+        }
+
+        let l = (to + 1 - from).ilog2() as usize;
+
+        trace!("Sparse table query: from: {}, to: {}, l: {}", from, to, l);
+
+        Ok(S::combine(
+            self.levels[l][from],
+            self.levels[l][to + 1 - (1 << l)],
+        ))
+    }
+}
+
+impl<S: Idempotent + MallocSizeOf> RangeQuery for SparseTable<S> {
+    fn new(numbers: Vec<u64>) -> Self {
+        SparseTable::new(numbers)
+    }
+
+    fn range_query(&self, from: usize, to: usize) -> Result<u64, RMQError> {
+        SparseTable::range_query(self, from, to)
+    }
+}
+
+// Disjoint sparse table, for every associative operation. On level h the
+// array is cut into blocks of 2^(h + 1) numbers. For every position the
+// level stores the combination from it to the middle of its block: up to
+// the middle for the left half, from the middle on for the right half.
+//
+// A range [from, to] with from != to crosses the middle of exactly one
+// block, on the level of the highest bit where from and to differ, and is
+// the left part of from combined with the right part of to. O(n log n)
+// space and construction, one combine per query.
+#[derive(MallocSizeOf, Clone)]
+pub struct DisjointSparseTable<S: Semigroup> {
+    numbers: Vec<u64>,
+    // levels[h][i], as above.
+    levels: Vec<Vec<u64>>,
+    operation: PhantomData<S>,
+}
+
+impl<S: Semigroup> DisjointSparseTable<S> {
+    pub fn new(numbers: Vec<u64>) -> Self {
+        let n = numbers.len();
+        // Levels up to the one whose block covers all positions.
+        let level_count = n.next_power_of_two().ilog2() as usize;
+
+        let mut levels = vec![vec![0u64; n]; level_count];
+
+        for (h, level) in levels.iter_mut().enumerate() {
+            let half = 1 << h;
+
+            for block_start in (0..n).step_by(2 * half) {
+                let middle = block_start + half;
+                if middle >= n {
+                    // No right half, never the crossing block of a range.
+                    break;
+                }
+
+                // Left half, from the middle towards the start.
+                level[middle - 1] = numbers[middle - 1];
+                for i in (block_start..middle - 1).rev() {
+                    level[i] = S::combine(numbers[i], level[i + 1]);
+                }
+
+                // Right half, from the middle towards the end.
+                let block_end = (middle + half).min(n);
+                level[middle] = numbers[middle];
+                for i in middle + 1..block_end {
+                    level[i] = S::combine(level[i - 1], numbers[i]);
+                }
+            }
+
+            debug!("Disjoint sparse table level {} done", h);
+        }
+
+        Self {
+            numbers,
+            levels,
+            operation: PhantomData,
+        }
+    }
+
+    pub fn range_query(&self, from: usize, to: usize) -> Result<u64, RMQError> {
+        if from > to || to >= self.numbers.len() {
+            return Err(RMQError::OutOfRange);
+        }
+
+        if from == to {
+            return Ok(self.numbers[from]);
+        }
+
+        let h = (from ^ to).ilog2() as usize;
+
+        trace!(
+            "Disjoint sparse table query: from: {}, to: {}, h: {}",
+            from,
+            to,
+            h
+        );
+
+        Ok(S::combine(self.levels[h][from], self.levels[h][to]))
+    }
+}
+
+impl<S: Semigroup + MallocSizeOf> RangeQuery for DisjointSparseTable<S> {
+    fn new(numbers: Vec<u64>) -> Self {
+        DisjointSparseTable::new(numbers)
+    }
+
+    fn range_query(&self, from: usize, to: usize) -> Result<u64, RMQError> {
+        DisjointSparseTable::range_query(self, from, to)
+    }
+}
+
+// The operations, to pick one at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Operation {
+    Sum,
+    Xor,
+    Gcd,
+    Max,
+    Min,
+}
+
+impl Operation {
+    pub fn combine(self, a: u64, b: u64) -> u64 {
+        match self {
+            Operation::Sum => Sum::combine(a, b),
+            Operation::Xor => Xor::combine(a, b),
+            Operation::Gcd => Gcd::combine(a, b),
+            Operation::Max => Max::combine(a, b),
+            Operation::Min => Min::combine(a, b),
+        }
+    }
+
+    // The sparse table for idempotent operations, unless disjoint is set,
+    // else the disjoint sparse table.
+    pub fn build(self, numbers: Vec<u64>, disjoint: bool) -> Box<dyn RangeQuery> {
+        match (self, disjoint) {
+            (Operation::Sum, _) => Box::new(DisjointSparseTable::<Sum>::new(numbers)),
+            (Operation::Xor, _) => Box::new(DisjointSparseTable::<Xor>::new(numbers)),
+            (Operation::Gcd, true) => Box::new(DisjointSparseTable::<Gcd>::new(numbers)),
+            (Operation::Max, true) => Box::new(DisjointSparseTable::<Max>::new(numbers)),
+            (Operation::Min, true) => Box::new(DisjointSparseTable::<Min>::new(numbers)),
+            (Operation::Gcd, false) => Box::new(SparseTable::<Gcd>::new(numbers)),
+            (Operation::Max, false) => Box::new(SparseTable::<Max>::new(numbers)),
+            (Operation::Min, false) => Box::new(SparseTable::<Min>::new(numbers)),
+        }
+    }
+}
+
+// Combines the numbers one by one. Oracle for range queries.
+pub fn range_query_naive(
+    numbers: &[u64],
+    operation: Operation,
+    from: usize,
+    to: usize,
+) -> Result<u64, RMQError> {
+    if from > to || to >= numbers.len() {
+        return Err(RMQError::OutOfRange);
+    }
+
+    Ok(numbers[from + 1..=to]
+        .iter()
+        .fold(numbers[from], |result, number| {
+            operation.combine(result, *number)
+        }))
+}
+
+// Benchmarks range queries under operation on an RMQ instance, the answers
+// are the combined values.
+pub fn benchmark(
+    path: &Path,
+    operation: Operation,
+    disjoint: bool,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    let instance = instances::stream_rmq_instance(path)?;
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    let range_query = operation.build(instance.numbers, disjoint);
+
+    let mut measurement = report::Measurement::new("range_query", start.elapsed());
+
+    for (i, query) in instance.queries.enumerate() {
+        let (from, to) = query?;
+
+        let start = Instant::now();
+        let got = range_query.range_query(from, to).unwrap();
+        measurement.record_query(start.elapsed());
+
+        writer.push(got);
+
+        if i % 100 == 0 {
+            debug!("Query nr {}", i);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = range_query.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
+}
+
+// Answers every query of the instance with the structure and with
+// range_query_naive. Returns the number of mismatches, the first few are
+// printed.
+pub fn check_against_naive(
+    path: &Path,
+    operation: Operation,
+    disjoint: bool,
+) -> Result<u64, InstanceError> {
+    let instance = instances::stream_rmq_instance(path)?;
+
+    let range_query = operation.build(instance.numbers.clone(), disjoint);
+    let numbers = instance.numbers;
+
+    let mut mismatches = 0;
+
+    for (i, query) in instance.queries.enumerate() {
+        let (from, to) = query?;
+
+        let want = range_query_naive(&numbers, operation, from, to);
+        let got = range_query.range_query(from, to);
+
+        if got != want {
+            if mismatches < 10 {
+                eprintln!(
+                    "Mismatch in query nr {}: {},{} want: {:?} got: {:?}",
+                    i, from, to, want, got
+                );
+            }
+            mismatches += 1;
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::ValueEnum;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn testing_range_query() {
+        let numbers = vec![12, 18, 5, 30, 7, 7, 42];
+
+        let sum = DisjointSparseTable::<Sum>::new(numbers.clone());
+        assert_eq!(Ok(121), sum.range_query(0, 6));
+        assert_eq!(Ok(53), sum.range_query(1, 3));
+        assert_eq!(Ok(30), sum.range_query(3, 3));
+        assert_eq!(Err(RMQError::OutOfRange), sum.range_query(3, 7));
+        assert_eq!(Err(RMQError::OutOfRange), sum.range_query(4, 3));
+
+        let gcd = SparseTable::<Gcd>::new(numbers.clone());
+        assert_eq!(Ok(6), gcd.range_query(0, 1));
+        assert_eq!(Ok(1), gcd.range_query(0, 2));
+        assert_eq!(Ok(7), gcd.range_query(4, 6));
+
+        let xor = DisjointSparseTable::<Xor>::new(numbers.clone());
+        assert_eq!(Ok(0), xor.range_query(4, 5));
+
+        let max = SparseTable::<Max>::new(numbers);
+        assert_eq!(Ok(30), max.range_query(0, 5));
+
+        let wrapping = DisjointSparseTable::<Sum>::new(vec![u64::MAX, 2]);
+        assert_eq!(Ok(1), wrapping.range_query(0, 1));
+
+        for table in [
+            Operation::Sum.build(vec![], false),
+            Operation::Min.build(vec![], false),
+        ] {
+            assert_eq!(Err(RMQError::OutOfRange), table.range_query(0, 0));
+        }
+    }
+
+    #[test]
+    fn testing_range_query_random() {
+        let mut rng = StdRng::seed_from_u64(12);
+
+        for n in [1, 2, 3, 4, 5, 8, 9, 31, 64, 100, 257] {
+            let numbers: Vec<u64> = (0..n).map(|_| rng.gen_range(0..1000) * 6).collect();
+
+            for operation in Operation::value_variants() {
+                for disjoint in [false, true] {
+                    let table = operation.build(numbers.clone(), disjoint);
+
+                    for from in 0..n {
+                        for to in from..n {
+                            assert_eq!(
+                                range_query_naive(&numbers, *operation, from, to),
+                                table.range_query(from, to),
+                                "{:?} {} {} {}",
+                                operation,
+                                disjoint,
+                                from,
+                                to
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
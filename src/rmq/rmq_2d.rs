@@ -0,0 +1,383 @@
+use std::path::Path;
+use std::time::Instant;
+
+use super::heapsize;
+use super::instances;
+use super::report;
+use super::{Algorithm, RMQError, RMQ};
+
+use crate::instances::InstanceError;
+use crate::malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use log::{debug, trace};
+
+// Rows [i, i + 2^k - 1] of the matrix squashed into one row: the minimum of
+// every column, with a one-dimensional RMQ over them.
+#[derive(MallocSizeOf)]
+struct Band {
+    // Row of the minimum of every column inside the band, the top one on
+    // ties.
+    minimum_rows: Vec<usize>,
+    // Over the column minima.
+    rmq: Box<dyn RMQ>,
+}
+
+// Range minimum queries on a matrix, answering the position (row, col) of
+// the minimum inside a rectangle.
+//
+// A sparse table over the rows, like RMQSparse: for every power of two 2^k
+// and every row i there is a band of the rows [i, i + 2^k - 1]. A rectangle
+// is covered by two overlapping bands of its height, each answers the
+// column range with its one-dimensional RMQ. O(1) queries with an O(1) RMQ,
+// rows log rows bands of cols numbers each.
+//
+// Which of several equal minima is returned depends on the RMQ over the
+// columns, like for the one-dimensional algorithms only the value is
+// fixed.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(MallocSizeOf)]
+pub struct RMQ2D {
+    rows: usize,
+    cols: usize,
+    // Row by row.
+    numbers: Vec<u64>,
+    // levels[k][i] is the band of the rows [i, i + 2^k - 1].
+    levels: Vec<Vec<Band>>,
+}
+
+impl RMQ2D {
+    // numbers holds the matrix row by row. algorithm is the RMQ over the
+    // columns of every band.
+    pub fn new(
+        numbers: Vec<u64>,
+        rows: usize,
+        cols: usize,
+        algorithm: Algorithm,
+    ) -> Result<Self, RMQError> {
+        if numbers.len() != rows * cols {
+            return Err(RMQError::InvalidMatrix {
+                rows,
+                cols,
+                len: numbers.len(),
+            });
+        }
+
+        let mut minimum_rows: Vec<Vec<usize>> = (0..rows).map(|row| vec![row; cols]).collect();
+        let mut levels: Vec<Vec<Band>> = Vec::new();
+
+        let mut k = 0;
+        while 1 << k <= rows {
+            if k > 0 {
+                // Band i of level k is made of the bands i and i + 2^(k - 1)
+                // of level k - 1, the top one wins ties.
+                let half = 1 << (k - 1);
+                minimum_rows = (0..=rows - (1 << k))
+                    .map(|i| {
+                        (0..cols)
+                            .map(|col| {
+                                let top = minimum_rows[i][col];
+                                let bottom = minimum_rows[i + half][col];
+
+                                if numbers[top * cols + col] <= numbers[bottom * cols + col] {
+                                    top
+                                } else {
+                                    bottom
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect();
+            }
+
+            let level = minimum_rows
+                .iter()
+                .map(|band_rows| {
+                    let minima = band_rows
+                        .iter()
+                        .enumerate()
+                        .map(|(col, row)| numbers[row * cols + col])
+                        .collect();
+
+                    Band {
+                        minimum_rows: band_rows.clone(),
+                        rmq: algorithm.build(minima),
+                    }
+                })
+                .collect();
+            levels.push(level);
+
+            debug!("2D RMQ level {}: {} bands", k, levels[k].len());
+
+            k += 1;
+        }
+
+        Ok(Self {
+            rows,
+            cols,
+            numbers,
+            levels,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    #[allow(dead_code)]
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> u64 {
+        self.numbers[row * self.cols + col]
+    }
+
+    // Position of the minimum in the band of the rows [i, i + 2^k - 1] and
+    // the columns [col_from, col_to].
+    fn in_band(
+        &self,
+        k: usize,
+        i: usize,
+        col_from: usize,
+        col_to: usize,
+    ) -> Result<(usize, usize), RMQError> {
+        let band = &self.levels[k][i];
+        let col = band.rmq.range_minimum_query(col_from, col_to)?;
+
+        Ok((band.minimum_rows[col], col))
+    }
+
+    // Position (row, col) of the minimum in the rectangle with the corners
+    // (row_from, col_from) and (row_to, col_to), both inclusive.
+    pub fn range_minimum_query(
+        &self,
+        row_from: usize,
+        col_from: usize,
+        row_to: usize,
+        col_to: usize,
+    ) -> Result<(usize, usize), RMQError> {
+        if row_from > row_to || col_from > col_to || row_to >= self.rows || col_to >= self.cols {
+            return Err(RMQError::OutOfRange);
+        }
+
+        let k = (row_to + 1 - row_from).ilog2() as usize;
+
+        // Overlapping bands of power-of-two height, like RMQSparse.
+        let top = self.in_band(k, row_from, col_from, col_to)?;
+        let bottom = self.in_band(k, row_to + 1 - (1 << k), col_from, col_to)?;
+
+        trace!(
+            "2D query: rows {}..={} cols {}..={}, k: {}, top: {:?}, bottom: {:?}",
+            row_from,
+            row_to,
+            col_from,
+            col_to,
+            k,
+            top,
+            bottom
+        );
+
+        if self.get(top.0, top.1) <= self.get(bottom.0, bottom.1) {
+            return Ok(top);
+        }
+
+        Ok(bottom)
+    }
+}
+
+// Looks at every number of the rectangle, row by row. Returns the topmost
+// of equal minima, then the leftmost. Oracle for RMQ2D.
+pub fn range_minimum_query_naive(
+    numbers: &[u64],
+    rows: usize,
+    cols: usize,
+    (row_from, col_from, row_to, col_to): (usize, usize, usize, usize),
+) -> Result<(usize, usize), RMQError> {
+    if row_from > row_to || col_from > col_to || row_to >= rows || col_to >= cols {
+        return Err(RMQError::OutOfRange);
+    }
+
+    let mut minimum = (row_from, col_from);
+
+    for row in row_from..=row_to {
+        for col in col_from..=col_to {
+            if numbers[row * cols + col] < numbers[minimum.0 * cols + minimum.1] {
+                minimum = (row, col);
+            }
+        }
+    }
+
+    Ok(minimum)
+}
+
+// Benchmarks RMQ2D on a matrix instance. Answers are written as the index
+// row * cols + col of the minimum.
+pub fn benchmark(
+    path: &Path,
+    algorithm: Algorithm,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    let instance = instances::read_matrix_instance(path)?;
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    // read_matrix_instance reads rows * cols numbers.
+    let rmq = RMQ2D::new(instance.numbers, instance.rows, instance.cols, algorithm).unwrap();
+
+    let mut measurement = report::Measurement::new("rmq2d", start.elapsed());
+
+    for (i, (row_from, col_from, row_to, col_to)) in instance.queries.iter().enumerate() {
+        let start = Instant::now();
+        let (row, col) = rmq
+            .range_minimum_query(*row_from, *col_from, *row_to, *col_to)
+            .unwrap();
+        measurement.record_query(start.elapsed());
+
+        writer.push((row * instance.cols + col) as u64);
+
+        if i % 100 == 0 {
+            debug!("Query nr {}", i);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = rmq.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
+}
+
+// Answers every query of the instance with RMQ2D and the naive oracle,
+// comparing the values at the answers. Returns the number of mismatches,
+// the first few are printed.
+pub fn check_against_naive(path: &Path, algorithm: Algorithm) -> Result<u64, InstanceError> {
+    let instance = instances::read_matrix_instance(path)?;
+    let (rows, cols) = (instance.rows, instance.cols);
+
+    let rmq = RMQ2D::new(instance.numbers.clone(), rows, cols, algorithm).unwrap();
+    let numbers = instance.numbers;
+
+    let mut mismatches = 0;
+
+    for (i, query) in instance.queries.iter().enumerate() {
+        let (row_from, col_from, row_to, col_to) = *query;
+
+        let want = range_minimum_query_naive(&numbers, rows, cols, *query).unwrap();
+        let got = rmq.range_minimum_query(row_from, col_from, row_to, col_to);
+
+        let correct = match got {
+            Ok((row, col)) => {
+                (row_from..=row_to).contains(&row)
+                    && (col_from..=col_to).contains(&col)
+                    && numbers[row * cols + col] == numbers[want.0 * cols + want.1]
+            }
+            Err(_) => false,
+        };
+
+        if !correct {
+            if mismatches < 10 {
+                eprintln!(
+                    "Mismatch in query nr {}: {:?} want: {:?} (value {}) got: {:?}",
+                    i,
+                    query,
+                    want,
+                    numbers[want.0 * cols + want.1],
+                    got
+                );
+            }
+            mismatches += 1;
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::ValueEnum;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn testing_rmq_2d() {
+        // 3 rows, 4 cols.
+        let numbers = vec![
+            9, 4, 7, 8, //
+            6, 5, 1, 3, //
+            2, 8, 6, 0, //
+        ];
+
+        for algorithm in Algorithm::value_variants() {
+            let rmq = RMQ2D::new(numbers.clone(), 3, 4, *algorithm).unwrap();
+
+            assert_eq!(Ok((2, 3)), rmq.range_minimum_query(0, 0, 2, 3));
+            assert_eq!(Ok((1, 2)), rmq.range_minimum_query(0, 1, 1, 3));
+            assert_eq!(Ok((0, 1)), rmq.range_minimum_query(0, 0, 0, 3));
+            assert_eq!(Ok((2, 0)), rmq.range_minimum_query(1, 0, 2, 1));
+            assert_eq!(Ok((1, 1)), rmq.range_minimum_query(1, 1, 1, 1));
+            assert_eq!(
+                Err(RMQError::OutOfRange),
+                rmq.range_minimum_query(0, 0, 3, 0)
+            );
+            assert_eq!(
+                Err(RMQError::OutOfRange),
+                rmq.range_minimum_query(0, 2, 0, 1)
+            );
+        }
+
+        assert_eq!(
+            Some(RMQError::InvalidMatrix {
+                rows: 2,
+                cols: 2,
+                len: 3
+            }),
+            RMQ2D::new(vec![1, 2, 3], 2, 2, Algorithm::Sparse).err()
+        );
+
+        let empty = RMQ2D::new(vec![], 0, 5, Algorithm::Sparse).unwrap();
+        assert_eq!(
+            Err(RMQError::OutOfRange),
+            empty.range_minimum_query(0, 0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn testing_rmq_2d_random() {
+        let mut rng = StdRng::seed_from_u64(13);
+
+        for _ in 0..30 {
+            let rows = rng.gen_range(1..20);
+            let cols = rng.gen_range(1..20);
+            let max_value = rng.gen_range(0..10);
+            let numbers: Vec<u64> = (0..rows * cols)
+                .map(|_| rng.gen_range(0..=max_value))
+                .collect();
+
+            for algorithm in Algorithm::value_variants() {
+                let rmq = RMQ2D::new(numbers.clone(), rows, cols, *algorithm).unwrap();
+
+                for _ in 0..50 {
+                    let (a, b) = (rng.gen_range(0..rows), rng.gen_range(0..rows));
+                    let (c, d) = (rng.gen_range(0..cols), rng.gen_range(0..cols));
+                    let query = (a.min(b), c.min(d), a.max(b), c.max(d));
+
+                    let want = range_minimum_query_naive(&numbers, rows, cols, query).unwrap();
+                    let got = rmq
+                        .range_minimum_query(query.0, query.1, query.2, query.3)
+                        .unwrap();
+
+                    assert_eq!(rmq.get(want.0, want.1), rmq.get(got.0, got.1));
+                    assert!((query.0..=query.2).contains(&got.0));
+                    assert!((query.1..=query.3).contains(&got.1));
+                }
+            }
+        }
+    }
+}
@@ -1,257 +1,166 @@
-use crate::debug::DEBUG;
-
 use super::RMQError;
 use super::RMQ;
 use std::collections::HashMap;
 
-use super::rmq_sparse::RMQSparse;
+use super::rmq_sparse_compact::RMQSparseCompact;
+use log::{debug, trace};
 
 #[derive(MallocSizeOf, Clone)]
 pub struct RMQSpanningBlocks {
     block_size: usize,
     block_count: usize,
     // Necessary for lookup of positions.
-    //numbers: Vec<u64>,
-    //2:
-    //block_minimum: Vec<u64>,
+    numbers: Vec<u64>,
     // Allows range minimum query over whole blocks.
-    block_minimum_sparse: RMQSparse,
+    block_minimum_sparse: RMQSparseCompact,
     // Wanna return position of minimum, not minimum itself.
-    block_minimum_position_in_block: Vec<usize>,
+    block_minimum_position: Vec<usize>,
 
-    //1:3:
     cartesian_trees: CartesianTrees,
 }
 
+// Largest block size, the cartesian tree number of a block needs two bits
+// per number and has to fit into a u64.
+pub const MAX_BLOCK_SIZE: usize = 31;
+
+// ceil(log2(n) / 4), at least 1.
+pub fn default_block_size(n: usize) -> usize {
+    (((n as f64).log2() / 4.0).ceil() as usize).max(1)
+}
+
 impl RMQSpanningBlocks {
     pub fn new(numbers: Vec<u64>) -> Self {
-        let n = (numbers.len() - 1) as f64;
+        let block_size = default_block_size(numbers.len());
 
-        let block_size = (n.log2() / 4.0).ceil() as usize;
-        let block_count = (n / block_size as f64).ceil() as usize;
+        Self::with_block_size(numbers, block_size).unwrap()
+    }
+
+    // Block size between 1 and MAX_BLOCK_SIZE. Larger blocks make the
+    // sparse table over the blocks smaller, but the in-block tables grow
+    // quadratically and there are more distinct cartesian trees.
+    pub fn with_block_size(numbers: Vec<u64>, block_size: usize) -> Result<Self, RMQError> {
+        check_block_size(block_size)?;
+
+        let n = numbers.len();
+        let block_count = n.div_ceil(block_size);
 
         // Query types:
         // 1) Zwei Teilblöcke + mehrere Blöcke
         // 2) umfasst ganze Blöcke: Blockgrenze zu Blockgrenze
         // 3) 1-2 Teilblöcke: Innerhalb eines Blocks oder eine grenze kreuzend.
 
-        //2:
-        // Stores minimum per whole block.
-        let mut block_minimum = vec![0u64; block_count as usize];
-
-        let block_minimum_position_in_block = vec![0usize; block_count as usize];
-
-        // Fill block_minimum and block_minimum_position_in_block.
-        let mut min = std::u64::MAX;
-        for i in 0..numbers.len() {
-            // Is i included in this block_minimum or only in the next?
-            //
-            // Determines whether to do this before or after.
-            if i % block_size == 0 {
-                // Store minimum of previous block.
-                block_minimum[i / block_size] = min;
-                min = std::u64::MAX;
-            }
+        // Stores minimum per whole block and where it is. The last block may
+        // be shorter than block_size.
+        let mut block_minimum = vec![u64::MAX; block_count];
+        let mut block_minimum_position = vec![0usize; block_count];
 
-            min = std::cmp::min(min, numbers[i]);
-        }
+        for (i, number) in numbers.iter().enumerate() {
+            let block = i / block_size;
 
-        // Write last block.
-        if numbers.len() % block_size == 0 {
-            // Store minimum of last block.
-            block_minimum[block_count - 1] = min;
+            // Leftmost minimum, like inside the blocks.
+            if i % block_size == 0 || *number < block_minimum[block] {
+                block_minimum[block] = *number;
+                block_minimum_position[block] = i;
+            }
         }
 
         // Verwende n log n-DS Sparse Table für B.
-        let block_minimum_sparse = RMQSparse::new(block_minimum);
+        let block_minimum_sparse = RMQSparseCompact::new(block_minimum);
 
         let cartesian_trees = CartesianTrees::new(&numbers, block_size, block_count);
 
-        return Self {
+        return Ok(Self {
             block_size: block_size,
             block_count: block_count,
-            //numbers: numbers,
-            //block_minimum: block_minimum,
+            numbers,
             block_minimum_sparse: block_minimum_sparse,
-            block_minimum_position_in_block: block_minimum_position_in_block,
+            block_minimum_position,
             cartesian_trees: cartesian_trees,
-        };
+        });
     }
 
-    fn number(&self, index: usize) -> u64 {
-        return self.block_minimum_sparse.numbers[index];
+    #[allow(dead_code)]
+    pub fn block_size(&self) -> usize {
+        self.block_size
     }
 
-    pub fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
-        let mut from_block = from / self.block_size;
-        let from_block_offset = from % self.block_size;
-        let mut to_block = to / self.block_size;
-        let to_block_offset = to % self.block_size;
-
-        if DEBUG {
-            println!(
-                "from_block: {}, from_block_offset: {}, to_block: {}, to_block_offset: {}",
-                from_block, from_block_offset, to_block, to_block_offset
-            );
-        }
-
-        if to_block - from_block != 0 {}
+    // Position of the minimum in [from, to] of one block, both offsets
+    // inside the block.
+    fn in_block(&self, block: usize, from: usize, to: usize) -> usize {
+        block * self.block_size + self.cartesian_trees.range_minimum_query(block, from, to)
+    }
 
-        // Same element.
-        if from_block_offset == to_block_offset && from_block == to_block {
-            return Ok(from);
+    pub fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
+        if from > to || to >= self.numbers.len() {
+            return Err(RMQError::OutOfRange);
         }
 
-        // 2: Whole blocks.
-        if from_block_offset == 0 && to_block_offset == 0 {
-            // Whole blocks.
-            if DEBUG {
-                println!(
-                "case2: from_block: {}, to_block: {}, from_block_offset: {}, to_block_offset: {}",
-                from_block, to_block, from_block_offset, to_block_offset
-            );
-            }
-
-            let block_minimum = self
-                .block_minimum_sparse
-                .range_minimum_query(from_block, to_block)?;
+        let from_block = from / self.block_size;
+        let from_block_offset = from % self.block_size;
+        let to_block = to / self.block_size;
+        let to_block_offset = to % self.block_size;
 
-            return Ok(block_minimum);
-        }
+        trace!(
+            "from_block: {}, from_block_offset: {}, to_block: {}, to_block_offset: {}",
+            from_block,
+            from_block_offset,
+            to_block,
+            to_block_offset
+        );
 
         // 3: Inside same block.
-        //
-        // If either offset is non-zero, do this case.
-        if from_block_offset != 0 || to_block_offset != 0 && from_block == to_block {
-            if DEBUG {
-                println!(
-                "case3.1: from_block: {}, to_block: {}, from_block_offset: {}, to_block_offset: {}",
-                from_block, to_block, from_block_offset, to_block_offset
-            );
-            }
-
-            return Ok(self.cartesian_trees.range_minimum_query(
-                from_block,
-                from_block_offset,
-                to_block_offset,
-            ));
+        if from_block == to_block {
+            return Ok(self.in_block(from_block, from_block_offset, to_block_offset));
         }
 
-        // 3: No blocks in-between with going over block border.
-        if from_block_offset != 0 && to_block_offset != 0 && from_block + 1 == to_block {
-            if DEBUG {
-                println!(
-                "case3.2: from_block: {}, to_block: {}, from_block_offset: {}, to_block_offset: {}",
-                from_block, to_block, from_block_offset, to_block_offset
-            );
-            }
-
-            let from_minimum_index = self.cartesian_trees.range_minimum_query(
-                from_block,
-                from_block_offset,
-                // Not sure whether to is correct here.
-                //
-                // Whether it is supposed to include the last element
-                // or not.
-                self.block_size - 1,
-            );
-            let from_min = self.number(from_minimum_index);
-
-            let to_minimum_index =
-                self.cartesian_trees
-                    .range_minimum_query(to_block, 0, to_block_offset);
-            let to_minimum = self.number(to_minimum_index);
+        // 1: Partial or whole first and last block, and the blocks in-between.
+        // Candidates are visited from left to right and only a strictly
+        // smaller one replaces the minimum, so ties resolve to the leftmost.
+        let mut min_index = self.in_block(from_block, from_block_offset, self.block_size - 1);
 
-            if from_min < to_minimum {
-                return Ok(from_minimum_index);
-            } else {
-                return Ok(to_minimum_index);
-            }
-        }
-
-        // 1: Blocks and one or two partial blocks.
-        if DEBUG {
-            println!(
-                "case1: from_block: {}, to_block: {}, from_block_offset: {}, to_block_offset: {}",
-                from_block, to_block, from_block_offset, to_block_offset
-            );
-        }
-
-        let mut min = std::u64::MAX;
-        let mut min_index = 0;
+        if from_block + 1 < to_block {
+            let block = self
+                .block_minimum_sparse
+                .range_minimum_query(from_block + 1, to_block - 1)?;
+            let block_minimum_index = self.block_minimum_position[block];
 
-        if from_block_offset != 0 {
-            // Do not include this block.
-            let from_minimum_index = self.cartesian_trees.range_minimum_query(
-                from_block,
-                from_block_offset,
-                self.block_size - 1,
+            trace!(
+                "case1.result: block: {} block_minimum_index: {}",
+                block,
+                block_minimum_index
             );
 
-            let from_minimum = self.number(from_minimum_index);
-
-            min = from_minimum;
-            min_index = from_minimum_index;
-
-            from_block += 1;
-
-            if DEBUG {
-                println!(
-                    "case1.result: from_minimum_index: {} from_minimum: {}",
-                    from_minimum_index, from_minimum
-                );
+            if self.numbers[block_minimum_index] < self.numbers[min_index] {
+                min_index = block_minimum_index;
             }
         }
 
-        if to_block_offset != 0 {
-            let to_minimum_index =
-                self.cartesian_trees
-                    .range_minimum_query(to_block, 0, to_block_offset);
-
-            let to_minimum = self.number(to_minimum_index);
-
-            if to_minimum < min {
-                min = to_minimum;
-                min_index = to_minimum_index;
-            }
-
-            if DEBUG {
-                println!(
-                    "case1.result: to_minimum_index: {} to_minimum: {}",
-                    to_minimum_index, to_minimum
-                );
-            }
-
-            // Do not include this block.
-            //
-            // Except.. is block_minimum_sparse inclusive or exclusive for to?
-            to_block -= 1;
+        let to_minimum_index = self.in_block(to_block, 0, to_block_offset);
+        if self.numbers[to_minimum_index] < self.numbers[min_index] {
+            min_index = to_minimum_index;
         }
 
-        let block_minimum_index = self
-            .block_minimum_sparse
-            .range_minimum_query(from_block, to_block)?;
-
-        let block_minimum = self.number(block_minimum_index);
-
-        if block_minimum < min {
-            min = block_minimum;
-            min_index = block_minimum_index;
-        }
-
-        if DEBUG {
-            println!(
-                "case1.result: block_minimum_index: {} block_minimum: {}",
-                block_minimum_index, block_minimum
-            );
-
-            print!("case1.result: end: min_index: {}, min: {}", min_index, min);
-        }
+        trace!(
+            "case1.result: end: min_index: {}, min: {}",
+            min_index,
+            self.numbers[min_index]
+        );
 
         return Ok(min_index);
     }
 }
 
+pub fn check_block_size(block_size: usize) -> Result<(), RMQError> {
+    if block_size == 0 || block_size > MAX_BLOCK_SIZE {
+        return Err(RMQError::InvalidBlockSize {
+            block_size,
+            max: MAX_BLOCK_SIZE,
+        });
+    }
+
+    Ok(())
+}
+
 impl RMQ for RMQSpanningBlocks {
     fn new(numbers: Vec<u64>) -> Self {
         RMQSpanningBlocks::new(numbers)
@@ -260,210 +169,242 @@ impl RMQ for RMQSpanningBlocks {
     fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
         RMQSpanningBlocks::range_minimum_query(self, from, to)
     }
+
+    fn numbers(&self) -> &[u64] {
+        &self.numbers
+    }
+}
+
+// Ballot numbers: ballot[p][q] is the number of ways to finish the stack
+// scan of a block with p numbers still to push and q pops still available,
+// never popping more than was pushed. ballot[s][s] is the Catalan number
+// C_s, the number of distinct cartesian trees of s nodes.
+fn ballot_numbers(s: usize) -> Vec<Vec<u64>> {
+    let mut ballot = vec![vec![0u64; s + 1]; s + 1];
+
+    for q in 0..=s {
+        ballot[0][q] = 1;
+        for p in 1..=q {
+            ballot[p][q] = ballot[p - 1][q] + ballot[p][q - 1];
+        }
+    }
+
+    ballot
 }
 
 #[derive(MallocSizeOf, Clone)]
 struct CartesianTrees {
     s: usize,
-    // Precomputed lookup table:
-    //   cartesian_tree_number -> [from][to]
+    // Row of every block in tables. With few possible cartesian trees that
+    // is the Catalan index of the block's tree, else the trees get rows in
+    // order of first appearance.
+    block_types: Vec<u32>,
+    // Flat lookup table, s * s offsets per row:
+    //   tables[(row * s + from) * s + to] = offset of the minimum of [from, to]
     //
-    // Not sure whether cartesian_tree_number needs 32 or 64 bit.
-    // s = log n / 2
-    // We get n = 1 000 000 -> s aufgerundet 5
-    //
-    // Should be enough either anyway.
-    cartesian_trees: HashMap<u64, Vec<Vec<usize>>>,
-    cartesian_tree_number_for_blocks: Vec<u64>,
+    // Offsets are below MAX_BLOCK_SIZE, so a byte each.
+    tables: Vec<u8>,
 }
 
 impl CartesianTrees {
-    pub fn new(array: &Vec<u64>, block_size: usize, block_count: usize) -> Self {
-        let n_float = array.len() as f64;
+    pub fn new(array: &[u64], block_size: usize, block_count: usize) -> Self {
+        let s = block_size;
+        let ballot = ballot_numbers(s);
+        let catalan = ballot[s][s];
+
+        // Index rows by Catalan index directly as long as that is no more
+        // rows than there are blocks, e.g. C_5 = 42 for n = 10^6.
+        let dense = catalan <= block_count as u64;
 
-        // Number of nodes in cartesian tree.
-        let s = (n_float.log2() / 4.0).ceil() as usize;
+        let rows = if dense { catalan as usize } else { 0 };
+        let mut tables: Vec<u8> = vec![0u8; rows * s * s];
+        let mut filled = vec![false; rows];
+        // Only while building, for trees too many to index directly.
+        let mut sparse_rows: HashMap<u64, u32> = HashMap::new();
 
-        let mut cartesian_trees: HashMap<u64, Vec<Vec<usize>>> = HashMap::new();
-        let mut cartesian_tree_number_for_blocks: Vec<u64> = vec![0u64; block_count];
+        let mut block_types: Vec<u32> = Vec::with_capacity(block_count);
+        let mut block = vec![0u64; s];
 
         for i in 0..block_count {
             let block_start = i * block_size;
-            let block_end = (i + 1) * block_size;
-
-            // Calculate cartesian tree number for block.
-            let cartesian_tree_number = Self::cartesian_tree_number(&array[block_start..block_end]);
-
-            cartesian_tree_number_for_blocks[i] = cartesian_tree_number;
-
-            if cartesian_trees.contains_key(&cartesian_tree_number) {
-                continue;
-            }
-
-            if DEBUG {
-                println!(
-                    "Precomputing cartesian tree number i={} cart_tree_number={:#b} n: {} s: {} block_size: {}, block_count: {}",
-                    i, cartesian_tree_number,
-                    n_float as u32,
-                    s,
-                    block_size, block_count
-                );
-            }
+            // The last block may be shorter.
+            let block_end = std::cmp::min((i + 1) * block_size, array.len());
+
+            // Pad the last block with maxima. Never popped and never the
+            // leftmost minimum, they give a tree with the same answers for
+            // the real numbers, and a full table for the row.
+            block[..block_end - block_start].copy_from_slice(&array[block_start..block_end]);
+            block[block_end - block_start..].fill(u64::MAX);
+
+            let index = Self::catalan_index(&block, &ballot);
+
+            let row = if dense {
+                let row = index as usize;
+                if filled[row] {
+                    block_types.push(row as u32);
+                    continue;
+                }
+                filled[row] = true;
+                row
+            } else {
+                let next = sparse_rows.len() as u32;
+                let row = *sparse_rows.entry(index).or_insert(next);
+                if row != next {
+                    block_types.push(row);
+                    continue;
+                }
+                tables.resize(tables.len() + s * s, 0);
+                row as usize
+            };
+
+            debug!(
+                "Precomputing cartesian tree i={} catalan_index={} n: {} s: {} block_count: {}",
+                i,
+                index,
+                array.len(),
+                s,
+                block_count
+            );
 
-            // Precompute RMQ structure for cartesian tree number.
-            cartesian_trees.insert(
-                cartesian_tree_number,
-                Self::precompute_for_cartesian_tree(&array[block_start..block_end]),
+            Self::precompute_for_cartesian_tree(
+                &block,
+                &mut tables[row * s * s..(row + 1) * s * s],
             );
+            block_types.push(row as u32);
         }
 
-        // Calculate cartesian tree number for each block.
-        // If cartesian tree number is already calculated, don't recompute,
-        // else precompute RMQ structure.
-
-        // Representable using 2s + 1 bits -> succinct trees.
-        // -> jeder mögliche cartesian tree mit 2^(2s + 1) bits darstellbar.
-        //
-        // -> Speichere für jeden möglichen cartesian tree und jede mögliche
-        // start-und Endposition das Ergebnis des rmq.
-        //
-        // !q Was ist der Unterschied zum direkt abspeichern aller Lösungen?
-        //
-        // Nur Wurzel(n) viele kartesische Bäume.
-        // Nur log^2(n) viele Start- und Endpositionen.
-        // -> Nur linear viel Platz.
-        //
-        // Lookup table: Lösung für Teilstück des blocks.
+        // At most sqrt(n) different trees of log2(n) / 4 numbers, each with
+        // a table of log^2(n) / 16 bytes: o(n) space.
 
         Self {
-            s: s,
-            cartesian_trees: cartesian_trees,
-            cartesian_tree_number_for_blocks: cartesian_tree_number_for_blocks,
+            s,
+            block_types,
+            tables,
         }
     }
 
     fn range_minimum_query(&self, block_number: usize, from: usize, to: usize) -> usize {
-        if DEBUG {
-            println!(
-                "CartesianTrees::range_minimum_query block_number: {}, from: {}, to: {}",
-                block_number, from, to
-            );
-        }
+        trace!(
+            "CartesianTrees::range_minimum_query block_number: {}, from: {}, to: {}",
+            block_number,
+            from,
+            to
+        );
 
-        return self.cartesian_trees[&self.cartesian_tree_number_for_blocks[block_number]][from]
-            [to];
-    }
+        let row = self.block_types[block_number] as usize;
 
-    /// Calculates cartesian tree number for given block.
-    ///
-    /// Uses push and pop operations that construction would entail for this.
-    fn cartesian_tree_number(block: &[u64]) -> u64 {
-        assert_ne!(block.len(), 0);
-        assert!(
-            // For each number, one bit for push and one for pop.
-            block.len() < 32,
-            "block too large for cartesian tree number"
-        );
+        self.tables[(row * self.s + from) * self.s + to] as usize
+    }
 
-        let mut stack: Vec<u64> = Vec::new();
+    // Calculates the Catalan index of the cartesian tree of the block, a
+    // number in 0..C_s that is equal for two blocks exactly if their trees
+    // are.
+    //
+    // Same stack scan as construction: every pop skips the ways to finish
+    // the scan that push at this point instead.
+    fn catalan_index(block: &[u64], ballot: &[Vec<u64>]) -> u64 {
+        let s = block.len();
 
-        let mut cartesian_number = 0;
+        let mut stack: Vec<u64> = Vec::with_capacity(s);
+        let mut index = 0;
+        // Pops still available.
+        let mut q = s;
 
-        for number in block {
+        for (i, number) in block.iter().enumerate() {
             // Pop all elements from stack that are greater than number.
-            while stack.len() > 0 && stack[stack.len() - 1] > *number {
+            while stack.last().is_some_and(|top| top > number) {
                 stack.pop();
 
-                // Keep new bit zero for pop.
-                cartesian_number <<= 1;
+                index += ballot[s - i - 1][q];
+                q -= 1;
             }
 
-            // Push number onto stack.
             stack.push(*number);
-
-            // Set new bit to 1 for push.
-            cartesian_number <<= 1;
-            cartesian_number |= 1;
         }
 
-        // Pop stack.
-        cartesian_number <<= stack.len();
-
-        return cartesian_number;
+        index
     }
 
-    fn precompute_for_cartesian_tree(block: &[u64]) -> Vec<Vec<usize>> {
+    fn precompute_for_cartesian_tree(block: &[u64], table: &mut [u8]) {
         let s = block.len();
 
-        let mut cartesian_tree_rmq: Vec<Vec<usize>> = vec![vec![0usize; s]; s];
-
-        // Precompute all possible [From][To] combinations.
+        // We only grow, so with each step we take on a new number. If it is
+        // smaller than the current minimum, record it.
         //
-        // a) [x] for i in 0..block.len(); for j in i..block.len()
-        //        But.. then what did I compute the cartesian tree for?
-        //        Can do that without anyway.
-        // b)     Use cartesian tree more than just a lookup of min value
-        //        -> walk cartesian tree?
-        //        - I know min from left to right.
-        for i in 0..block.len() {
+        // Equal numbers keep the current one, as the stack scan only pops
+        // greater numbers.
+        for i in 0..s {
             // Minimum to itself.
-            cartesian_tree_rmq[i][i] = i;
+            table[i * s + i] = i as u8;
 
-            for j in i + 1..block.len() {
-                if DEBUG {
-                    println!("Precomputing i: {}, j: {}", i, j);
-                }
+            for j in i + 1..s {
+                let current = table[i * s + j - 1];
 
-                // We only grow, so with each step we take on a new number.
-                //
-                // If the new number is smaller than the current, then record
-                // it. Else keep the current minimum.
-                if block[cartesian_tree_rmq[i][j - 1]] < block[j] {
-                    cartesian_tree_rmq[i][j] = cartesian_tree_rmq[i][j - 1];
+                table[i * s + j] = if block[current as usize] <= block[j] {
+                    current
                 } else {
-                    cartesian_tree_rmq[i][j] = j;
-                }
+                    j as u8
+                };
             }
         }
-
-        return cartesian_tree_rmq;
     }
+}
 
-    // fn set_right_child(node: u64, new_right_child: u32) -> u64 {
-    //     let (value, left_child, _) = Self::unpack_values(node);
+#[cfg(test)]
+mod tests {
+    use super::{ballot_numbers, CartesianTrees, RMQSpanningBlocks};
 
-    //     return Self::pack_values(value, left_child, new_right_child);
-    // }
+    use crate::rmq::cartesian_tree::CartesianTree;
+    use std::collections::HashMap;
 
-    // fn pack_values(value1: u32, value2: u32, value3: u32) -> u64 {
-    //     assert!(value1 < (1 << 20));
-    //     assert!(value2 < (1 << 20));
-    //     assert!(value3 < (1 << 20));
+    #[test]
+    fn test_catalan_index() {
+        let ballot = ballot_numbers(9);
+        assert_eq!(4862, ballot[9][9]);
+        assert_eq!(14544636039226909, ballot_numbers(31)[31][31]);
 
-    //     // Shift left and or-ing to pack.
-    //     let packed = ((value1 as u64) << 40) | ((value2 as u64) << 20) | (value3 as u64);
-    //     packed
-    // }
+        let array = vec![3, 2, 4, 4, 5, 2, 4, 7, 6];
+        assert_eq!(3666, CartesianTrees::catalan_index(&array, &ballot));
 
-    // fn unpack_values(packed: u64) -> (u32, u32, u32) {
-    //     let value1 = ((packed >> 40) & ((1 << 20) - 1)) as u32;
-    //     let value2 = ((packed >> 20) & ((1 << 20) - 1)) as u32;
-    //     let value3 = (packed & ((1 << 20) - 1)) as u32;
-    //     (value1, value2, value3)
-    // }
-}
+        // Same tree, same index.
+        let same: Vec<u64> = array.iter().map(|number| number * 2 + 1).collect();
+        assert_eq!(3666, CartesianTrees::catalan_index(&same, &ballot));
 
-mod tests {
+        // Every array of 5 numbers from 0..5: each of the C_5 = 42 trees
+        // gets its own index in 0..42, matching the shape code.
+        let ballot = ballot_numbers(5);
+        let mut indices: HashMap<u64, Option<u64>> = HashMap::new();
 
-    #[allow(unused_imports)]
-    use super::CartesianTrees;
+        for code in 0..5usize.pow(5) {
+            let block: Vec<u64> = (0..5).map(|i| (code / 5usize.pow(i) % 5) as u64).collect();
 
-    #[test]
-    fn test_cartesian_tree_number() {
-        let array = vec![3, 2, 4, 4, 5, 2, 4, 7, 6];
+            let index = CartesianTrees::catalan_index(&block, &ballot);
+            let shape = CartesianTree::new(&block).shape_number();
 
-        let cartesian_tree_number = CartesianTrees::cartesian_tree_number(&array);
+            assert_eq!(shape, *indices.entry(index).or_insert(shape), "{:?}", block);
+        }
+
+        assert_eq!(42, indices.len());
+        assert!(indices.keys().all(|index| *index < 42));
+    }
 
-        assert_eq!(cartesian_tree_number, 0b101111000111010000);
+    #[test]
+    fn test_dense_and_sparse_rows() {
+        // 1000 blocks of 3: direct Catalan rows. 40 blocks of 9: far fewer
+        // blocks than the 4862 possible trees, rows by first appearance.
+        for (n, block_size, rows) in [(3000, 3, 5), (360, 9, 40)] {
+            let numbers: Vec<u64> = (0..n).map(|i| (i * 7919 % 13) as u64).collect();
+            let rmq = RMQSpanningBlocks::with_block_size(numbers.clone(), block_size).unwrap();
+
+            assert!(rmq.cartesian_trees.tables.len() <= rows * block_size * block_size);
+
+            for from in (0..n).step_by(7) {
+                for to in (from..n.min(from + 30)).step_by(3) {
+                    let got = rmq.range_minimum_query(from, to).unwrap();
+                    let want = (from..=to).min_by_key(|i| (numbers[*i], *i)).unwrap();
+                    assert_eq!(want, got);
+                }
+            }
+        }
     }
 }
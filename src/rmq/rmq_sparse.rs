@@ -1,7 +1,6 @@
-use crate::debug::DEBUG;
-
 use super::RMQError;
 use super::RMQ;
+use log::trace;
 
 #[derive(MallocSizeOf, Clone)]
 pub struct RMQSparse {
@@ -23,11 +22,12 @@ impl RMQSparse {
         }
 
         let mut j = 1;
-        while 1 << j < n {
+        while 1 << j <= n {
             // Compute minimum of every range of length 2^j
             let mut i = 0;
             while i + (1 << j) - 1 < n {
-                if numbers[m[i][j - 1]] < numbers[m[i + (1 << (j - 1))][j - 1]] {
+                // Ties go to the left, so the leftmost minimum is found.
+                if numbers[m[i][j - 1]] <= numbers[m[i + (1 << (j - 1))][j - 1]] {
                     m[i][j] = m[i][j - 1];
                 } else {
                     m[i][j] = m[i + (1 << (j - 1))][j - 1];
@@ -46,25 +46,25 @@ impl RMQSparse {
     }
 
     pub fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
-        if DEBUG {
-            println!("Sparse query: from: {}, to: {}", from, to);
+        trace!("Sparse query: from: {}, to: {}", from, to);
+
+        if from > to || to >= self.numbers.len() {
+            return Err(RMQError::OutOfRange);
         }
 
         let l = ((to + 1 - from) as f64).log2().floor() as usize;
 
         // Overlapping ranges of power-of-two length.
         // No issue, because we are looking for the minimum.
-        let m1 = self.m[from][l] as usize;
-        let m2 = self.m[to - (1 << l)][l] as usize;
+        let m1 = self.m[from][l];
+        let m2 = self.m[to + 1 - (1 << l)][l];
 
-        if DEBUG {
-            println!(
-            "Sparse query: from: {}, to: {}, l: {}, m1_index: {}, m2_index: {}, m1_value: {}, m2_value: {}",
-            from, to, l, m1, m2, self.numbers[m1], self.numbers[m2]
+        trace!(
+        "Sparse query: from: {}, to: {}, l: {}, m1_index: {}, m2_index: {}, m1_value: {}, m2_value: {}",
+        from, to, l, m1, m2, self.numbers[m1], self.numbers[m2]
         );
-        }
 
-        if self.numbers[m1] < self.numbers[m2] {
+        if self.numbers[m1] <= self.numbers[m2] {
             return Ok(m1);
         }
 
@@ -80,4 +80,8 @@ impl RMQ for RMQSparse {
     fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
         RMQSparse::range_minimum_query(self, from, to)
     }
+
+    fn numbers(&self) -> &[u64] {
+        &self.numbers
+    }
 }
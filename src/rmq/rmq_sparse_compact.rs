@@ -0,0 +1,192 @@
+use super::RMQError;
+use super::RMQ;
+use log::{debug, trace};
+
+// Sparse table like RMQSparse, but with the levels one after another in a
+// single bit-packed array. The entry for [i, i + 2^j - 1] stores the
+// position of the minimum relative to i, which is below 2^j and so takes
+// j bits. Level 0 is always 0 and not stored at all.
+//
+// Sum of j (n - 2^j + 1) bits for the offsets in total, about
+// n log^2 n / 2, against 64 n (log n + 1) for RMQSparse, with the same two
+// lookups per query.
+#[derive(MallocSizeOf, Clone)]
+pub struct RMQSparseCompact {
+    // Level j has n - 2^j + 1 entries of j bits each.
+    bits: Vec<u64>,
+    // Bit position where level j starts, one more for the end.
+    level_starts: Vec<usize>,
+    pub numbers: Vec<u64>,
+}
+
+impl RMQSparseCompact {
+    pub fn new(numbers: Vec<u64>) -> Self {
+        let n = numbers.len();
+        let k = if n == 0 { 0 } else { n.ilog2() as usize };
+
+        let mut level_starts = vec![0usize; k + 2];
+        for j in 1..=k {
+            level_starts[j + 1] = level_starts[j] + (n + 1 - (1 << j)) * j;
+        }
+
+        let mut table = Self {
+            bits: vec![0u64; level_starts[k + 1].div_ceil(64)],
+            level_starts,
+            numbers,
+        };
+
+        for j in 1..=k {
+            let half = 1 << (j - 1);
+
+            for i in 0..=n - (1 << j) {
+                // Ties go to the left, so the leftmost minimum is found.
+                let left = table.position(i, j - 1);
+                let right = table.position(i + half, j - 1);
+
+                let minimum = if table.numbers[left] <= table.numbers[right] {
+                    left
+                } else {
+                    right
+                };
+
+                table.set(j, i, (minimum - i) as u64);
+            }
+
+            debug!("Compact sparse level {}: {} bits", j, table.level_bits(j));
+        }
+
+        table
+    }
+
+    fn level_bits(&self, j: usize) -> usize {
+        self.level_starts[j + 1] - self.level_starts[j]
+    }
+
+    // Entries are only written once, into zeroed bits.
+    fn set(&mut self, j: usize, i: usize, offset: u64) {
+        let bit = self.level_starts[j] + i * j;
+        let word = bit / 64;
+        let shift = bit % 64;
+
+        self.bits[word] |= offset << shift;
+        if shift + j > 64 {
+            self.bits[word + 1] |= offset >> (64 - shift);
+        }
+    }
+
+    // Position of the minimum of [i, i + 2^j - 1].
+    fn position(&self, i: usize, j: usize) -> usize {
+        if j == 0 {
+            return i;
+        }
+
+        let bit = self.level_starts[j] + i * j;
+        let word = bit / 64;
+        let shift = bit % 64;
+
+        let mut offset = self.bits[word] >> shift;
+        // Entry continues in the next word.
+        if shift + j > 64 {
+            offset |= self.bits[word + 1] << (64 - shift);
+        }
+
+        i + (offset & ((1 << j) - 1)) as usize
+    }
+
+    pub fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
+        if from > to || to >= self.numbers.len() {
+            return Err(RMQError::OutOfRange);
+        }
+
+        let l = (to + 1 - from).ilog2() as usize;
+
+        // Overlapping ranges of power-of-two length, like RMQSparse.
+        let m1 = self.position(from, l);
+        let m2 = self.position(to + 1 - (1 << l), l);
+
+        trace!(
+            "Compact sparse query: from: {}, to: {}, l: {}, m1_index: {}, m2_index: {}",
+            from,
+            to,
+            l,
+            m1,
+            m2
+        );
+
+        if self.numbers[m1] <= self.numbers[m2] {
+            return Ok(m1);
+        }
+
+        Ok(m2)
+    }
+}
+
+impl RMQ for RMQSparseCompact {
+    fn new(numbers: Vec<u64>) -> Self {
+        RMQSparseCompact::new(numbers)
+    }
+
+    fn range_minimum_query(&self, from: usize, to: usize) -> Result<usize, RMQError> {
+        RMQSparseCompact::range_minimum_query(self, from, to)
+    }
+
+    fn numbers(&self) -> &[u64] {
+        &self.numbers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::heapsize;
+    use crate::malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+    use crate::rmq::rmq_sparse::RMQSparse;
+
+    #[test]
+    fn testing_sparse_compact() {
+        let mut rng = StdRng::seed_from_u64(11);
+
+        // Lengths around powers of two, so entries cross word boundaries
+        // at every level.
+        for n in [1usize, 2, 3, 63, 64, 65, 127, 128, 129, 1000] {
+            let numbers: Vec<u64> = (0..n).map(|_| rng.gen_range(0..20)).collect();
+
+            let compact = RMQSparseCompact::new(numbers.clone());
+            let sparse = RMQSparse::new(numbers.clone());
+
+            let k = n.ilog2() as usize;
+            for j in 1..=k {
+                assert_eq!((n + 1 - (1 << j)) * j, compact.level_bits(j));
+            }
+
+            for _ in 0..500 {
+                let a = rng.gen_range(0..n);
+                let b = rng.gen_range(0..n);
+                let (from, to) = (a.min(b), a.max(b));
+
+                assert_eq!(
+                    sparse.range_minimum_query(from, to),
+                    compact.range_minimum_query(from, to),
+                    "{} {}",
+                    from,
+                    to
+                );
+            }
+
+            assert_eq!(Err(RMQError::OutOfRange), compact.range_minimum_query(0, n));
+        }
+
+        let empty = RMQSparseCompact::new(vec![]);
+        assert_eq!(Err(RMQError::OutOfRange), empty.range_minimum_query(0, 0));
+
+        let numbers: Vec<u64> = (0..10_000).map(|_| rng.gen()).collect();
+        let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+        let compact = RMQSparseCompact::new(numbers.clone()).size_of(&mut ops);
+        let sparse = RMQSparse::new(numbers).size_of(&mut ops);
+        assert!(compact * 3 < sparse, "{} {}", compact, sparse);
+    }
+}
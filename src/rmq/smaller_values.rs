@@ -0,0 +1,389 @@
+use std::path::Path;
+use std::time::Instant;
+
+use super::heapsize;
+use super::instances;
+use super::report;
+use super::{Algorithm, RMQError, RMQ};
+
+use crate::instances::InstanceError;
+use crate::malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use log::debug;
+
+// Marks a position without a smaller value on that side.
+const NONE: usize = usize::MAX;
+
+fn optional(position: usize) -> Option<usize> {
+    match position {
+        NONE => None,
+        position => Some(position),
+    }
+}
+
+// Previous and next smaller values (PSV/NSV) of every position: the
+// nearest position to the left and to the right with a strictly smaller
+// number.
+#[derive(MallocSizeOf, Clone)]
+pub struct SmallerValues {
+    previous: Vec<usize>,
+    next: Vec<usize>,
+}
+
+impl SmallerValues {
+    // Linear time, one pass from each side. The stack holds the positions
+    // that can still be the answer for a later position, increasing in
+    // number. Everything not smaller than the current number is popped, it
+    // is hidden behind the current position from then on.
+    pub fn new(numbers: &[u64]) -> Self {
+        let n = numbers.len();
+
+        let mut previous = vec![NONE; n];
+        let mut next = vec![NONE; n];
+        let mut stack: Vec<usize> = Vec::new();
+
+        for i in 0..n {
+            while stack.last().is_some_and(|top| numbers[*top] >= numbers[i]) {
+                stack.pop();
+            }
+            previous[i] = stack.last().copied().unwrap_or(NONE);
+            stack.push(i);
+        }
+
+        stack.clear();
+        for i in (0..n).rev() {
+            while stack.last().is_some_and(|top| numbers[*top] >= numbers[i]) {
+                stack.pop();
+            }
+            next[i] = stack.last().copied().unwrap_or(NONE);
+            stack.push(i);
+        }
+
+        Self { previous, next }
+    }
+
+    pub fn previous(&self, i: usize) -> Option<usize> {
+        optional(self.previous[i])
+    }
+
+    pub fn next(&self, i: usize) -> Option<usize> {
+        optional(self.next[i])
+    }
+
+    // Next smaller value at most d positions after i.
+    pub fn next_within(&self, i: usize, d: usize) -> Option<usize> {
+        self.next(i).filter(|next| next - i <= d)
+    }
+}
+
+// First position j in [from, to] with a number smaller than bound, going
+// right. The minimum of [from, j] drops below bound exactly from the answer
+// on, so doubling the window and then halving it takes O(log(j - from))
+// range minimum queries.
+fn first_smaller<R: RMQ + ?Sized>(
+    rmq: &R,
+    from: usize,
+    to: usize,
+    bound: u64,
+) -> Result<Option<usize>, RMQError> {
+    if from > to {
+        return Ok(None);
+    }
+
+    let numbers = rmq.numbers();
+    let smaller = |end: usize| -> Result<bool, RMQError> {
+        Ok(numbers[rmq.range_minimum_query(from, end)?] < bound)
+    };
+
+    // Window [from, from + len - 1], doubled until it has a smaller number.
+    let mut len = 1;
+    loop {
+        let end = (from + len - 1).min(to);
+        if smaller(end)? {
+            break;
+        }
+        if end == to {
+            return Ok(None);
+        }
+        len *= 2;
+    }
+
+    // The answer is in the last doubling, the first half had none.
+    let mut low = from + len / 2;
+    let mut high = (from + len - 1).min(to);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if smaller(mid)? {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+
+    Ok(Some(low))
+}
+
+// Last position j in [from, to] with a number smaller than bound, going
+// left. Mirror of first_smaller.
+fn last_smaller<R: RMQ + ?Sized>(
+    rmq: &R,
+    from: usize,
+    to: usize,
+    bound: u64,
+) -> Result<Option<usize>, RMQError> {
+    if from > to {
+        return Ok(None);
+    }
+
+    let numbers = rmq.numbers();
+    let smaller = |start: usize| -> Result<bool, RMQError> {
+        Ok(numbers[rmq.range_minimum_query(start, to)?] < bound)
+    };
+
+    let mut len = 1;
+    loop {
+        let start = to.saturating_sub(len - 1).max(from);
+        if smaller(start)? {
+            break;
+        }
+        if start == from {
+            return Ok(None);
+        }
+        len *= 2;
+    }
+
+    let mut low = to.saturating_sub(len - 1).max(from);
+    let mut high = to - len / 2;
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        if smaller(mid)? {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    Ok(Some(low))
+}
+
+// On-demand PSV of i over any RMQ, without precomputed arrays.
+pub fn previous_smaller<R: RMQ + ?Sized>(rmq: &R, i: usize) -> Result<Option<usize>, RMQError> {
+    let numbers = rmq.numbers();
+    if i >= numbers.len() {
+        return Err(RMQError::OutOfRange);
+    }
+
+    match i {
+        0 => Ok(None),
+        i => last_smaller(rmq, 0, i - 1, numbers[i]),
+    }
+}
+
+// On-demand NSV of i over any RMQ.
+pub fn next_smaller<R: RMQ + ?Sized>(rmq: &R, i: usize) -> Result<Option<usize>, RMQError> {
+    next_smaller_within(rmq, i, rmq.numbers().len())
+}
+
+// On-demand NSV of i, only looking at most d positions ahead. Takes
+// O(log d) range minimum queries however far the next smaller value is.
+pub fn next_smaller_within<R: RMQ + ?Sized>(
+    rmq: &R,
+    i: usize,
+    d: usize,
+) -> Result<Option<usize>, RMQError> {
+    let numbers = rmq.numbers();
+    if i >= numbers.len() {
+        return Err(RMQError::OutOfRange);
+    }
+
+    let to = i.saturating_add(d).min(numbers.len() - 1);
+
+    first_smaller(rmq, i + 1, to, numbers[i])
+}
+
+// Finds the previous and next smaller value of every position of the
+// numbers of an RMQ instance on demand, over the RMQ built with algorithm.
+// With within, the next one only at most that many positions ahead. The
+// queries of the instance are not used, the previous and then the next
+// smaller value of every position are written.
+pub fn benchmark(
+    path: &Path,
+    algorithm: Algorithm,
+    within: Option<usize>,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    let numbers = instances::stream_rmq_instance(path)?.numbers;
+    let n = numbers.len();
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    let rmq = algorithm.build(numbers);
+
+    let mut measurement = report::Measurement::new("smaller-values", start.elapsed());
+
+    // Every i is a position, so no query fails.
+    for i in 0..n {
+        let start = Instant::now();
+        let previous = rmq.previous_smaller(i).unwrap();
+        let next = match within {
+            Some(d) => rmq.next_smaller_within(i, d),
+            None => rmq.next_smaller(i),
+        }
+        .unwrap();
+        measurement.record_query(start.elapsed());
+
+        // u64::MAX for none.
+        for position in [previous, next] {
+            writer.push(position.map_or(u64::MAX, |position| position as u64));
+        }
+
+        if i % 100 == 0 {
+            debug!("Position nr {}", i);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = rmq.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
+}
+
+// Compares the answers of benchmark with the precomputed SmallerValues for
+// every position. Returns the number of mismatches, the first few are
+// printed.
+pub fn check_against_precomputed(
+    path: &Path,
+    algorithm: Algorithm,
+    within: Option<usize>,
+) -> Result<u64, InstanceError> {
+    let numbers = instances::stream_rmq_instance(path)?.numbers;
+
+    let precomputed = SmallerValues::new(&numbers);
+    let rmq = algorithm.build(numbers);
+
+    let mut mismatches = 0;
+
+    for i in 0..rmq.numbers().len() {
+        let (want, got) = match within {
+            Some(d) => (
+                (precomputed.previous(i), precomputed.next_within(i, d)),
+                (rmq.previous_smaller(i), rmq.next_smaller_within(i, d)),
+            ),
+            None => (
+                (precomputed.previous(i), precomputed.next(i)),
+                (rmq.previous_smaller(i), rmq.next_smaller(i)),
+            ),
+        };
+
+        if (Ok(want.0), Ok(want.1)) != got {
+            if mismatches < 10 {
+                eprintln!(
+                    "Mismatch at position {}: want: {:?} got: {:?}",
+                    i, want, got
+                );
+            }
+            mismatches += 1;
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::ValueEnum;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::rmq::Algorithm;
+
+    fn previous_naive(numbers: &[u64], i: usize) -> Option<usize> {
+        (0..i).rev().find(|j| numbers[*j] < numbers[i])
+    }
+
+    fn next_naive(numbers: &[u64], i: usize) -> Option<usize> {
+        (i + 1..numbers.len()).find(|j| numbers[*j] < numbers[i])
+    }
+
+    #[test]
+    fn testing_smaller_values() {
+        let numbers = [3, 1, 4, 1, 5, 9, 2, 6, 5, 3];
+        let smaller_values = SmallerValues::new(&numbers);
+
+        assert_eq!(None, smaller_values.previous(1));
+        assert_eq!(None, smaller_values.next(1));
+        assert_eq!(Some(3), smaller_values.previous(4));
+        assert_eq!(Some(6), smaller_values.next(4));
+        assert_eq!(Some(6), smaller_values.next(5));
+        assert_eq!(Some(6), smaller_values.previous(7));
+        assert_eq!(None, smaller_values.next_within(4, 1));
+        assert_eq!(Some(6), smaller_values.next_within(4, 2));
+
+        for algorithm in Algorithm::value_variants() {
+            let rmq = algorithm.build(numbers.to_vec());
+
+            assert_eq!(Ok(Some(6)), rmq.next_smaller(4));
+            assert_eq!(Ok(None), rmq.next_smaller_within(4, 1));
+            assert_eq!(Ok(Some(1)), rmq.previous_smaller(2));
+            assert_eq!(Err(RMQError::OutOfRange), rmq.next_smaller(10));
+            assert_eq!(Err(RMQError::OutOfRange), rmq.previous_smaller(10));
+        }
+    }
+
+    #[test]
+    fn testing_smaller_values_instance() {
+        let path = Path::new("testdata/rmq_examples/rmq_example_2.txt");
+
+        for within in [None, Some(0), Some(3)] {
+            assert_eq!(
+                0,
+                check_against_precomputed(path, Algorithm::SpanningBlocks, within).unwrap()
+            );
+        }
+        assert_eq!(
+            0,
+            check_against_precomputed(path, Algorithm::SparseCompact, Some(10)).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_smaller_values_random() {
+        let mut rng = StdRng::seed_from_u64(8);
+
+        for _ in 0..100 {
+            let n = rng.gen_range(1..100);
+            let max_value = rng.gen_range(0..10);
+            let numbers: Vec<u64> = (0..n).map(|_| rng.gen_range(0..=max_value)).collect();
+
+            let smaller_values = SmallerValues::new(&numbers);
+            let rmqs: Vec<_> = Algorithm::value_variants()
+                .iter()
+                .map(|algorithm| algorithm.build(numbers.clone()))
+                .collect();
+
+            for i in 0..n {
+                let previous = previous_naive(&numbers, i);
+                let next = next_naive(&numbers, i);
+                let d = rng.gen_range(0..n);
+                let within = next.filter(|next| next - i <= d);
+
+                assert_eq!(previous, smaller_values.previous(i), "{:?} {}", numbers, i);
+                assert_eq!(next, smaller_values.next(i), "{:?} {}", numbers, i);
+                assert_eq!(within, smaller_values.next_within(i, d));
+
+                for rmq in &rmqs {
+                    assert_eq!(Ok(previous), rmq.previous_smaller(i));
+                    assert_eq!(Ok(next), rmq.next_smaller(i));
+                    assert_eq!(Ok(within), rmq.next_smaller_within(i, d));
+                }
+            }
+        }
+    }
+}
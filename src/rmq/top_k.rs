@@ -0,0 +1,267 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::Path;
+use std::time::Instant;
+
+use super::heapsize;
+use super::instances;
+use super::report;
+use super::{Algorithm, RMQError, RMQ};
+
+use crate::instances::InstanceError;
+use crate::malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use log::debug;
+
+// Queries beyond the single minimum, built only on range_minimum_query:
+// the minimum of a range splits it into the parts left and right of it,
+// which hold everything else.
+
+// The k smallest numbers in [from, to] as (position, number), ascending by
+// number and then by position. Fewer if the range is shorter.
+//
+// A heap holds the minimum of every part not yet split, so this takes k
+// range minimum queries and O(k log k) time. Of equal numbers at the cut
+// after the k-th, which positions are reported depends on how the RMQ
+// resolves ties.
+pub fn top_k<R: RMQ + ?Sized>(
+    rmq: &R,
+    from: usize,
+    to: usize,
+    k: usize,
+) -> Result<Vec<(usize, u64)>, RMQError> {
+    let numbers = rmq.numbers();
+    if from > to || to >= numbers.len() {
+        return Err(RMQError::OutOfRange);
+    }
+
+    let mut smallest = Vec::with_capacity(k.min(to - from + 1));
+
+    // Smallest number first, then leftmost position.
+    let mut parts = BinaryHeap::new();
+    let minimum = rmq.range_minimum_query(from, to)?;
+    parts.push(Reverse((numbers[minimum], minimum, from, to)));
+
+    while smallest.len() < k {
+        let Reverse((number, position, from, to)) = match parts.pop() {
+            Some(part) => part,
+            None => break,
+        };
+
+        smallest.push((position, number));
+
+        if position > from {
+            let minimum = rmq.range_minimum_query(from, position - 1)?;
+            parts.push(Reverse((numbers[minimum], minimum, from, position - 1)));
+        }
+        if position < to {
+            let minimum = rmq.range_minimum_query(position + 1, to)?;
+            parts.push(Reverse((numbers[minimum], minimum, position + 1, to)));
+        }
+    }
+
+    // An RMQ that resolves ties to the right finds equal numbers from right
+    // to left.
+    smallest.sort_unstable_by_key(|(position, number)| (*number, *position));
+
+    Ok(smallest)
+}
+
+// Every position in [from, to] holding the minimum, ascending.
+//
+// Parts whose minimum is larger are not split further, so this takes
+// 2 occ + 1 range minimum queries for occ positions.
+pub fn all_minimum_positions<R: RMQ + ?Sized>(
+    rmq: &R,
+    from: usize,
+    to: usize,
+) -> Result<Vec<usize>, RMQError> {
+    let numbers = rmq.numbers();
+    if from > to || to >= numbers.len() {
+        return Err(RMQError::OutOfRange);
+    }
+
+    let position = rmq.range_minimum_query(from, to)?;
+    let minimum = numbers[position];
+
+    let mut positions = Vec::new();
+    // Parts with the position of their minimum.
+    let mut parts = vec![(from, to, position)];
+
+    while let Some((from, to, position)) = parts.pop() {
+        if numbers[position] != minimum {
+            continue;
+        }
+
+        positions.push(position);
+
+        if position > from {
+            let minimum = rmq.range_minimum_query(from, position - 1)?;
+            parts.push((from, position - 1, minimum));
+        }
+        if position < to {
+            let minimum = rmq.range_minimum_query(position + 1, to)?;
+            parts.push((position + 1, to, minimum));
+        }
+    }
+
+    positions.sort_unstable();
+
+    Ok(positions)
+}
+
+// Answers the queries of an RMQ instance with the positions of the k
+// smallest numbers of every range, or with None all positions of its
+// minimum, over the RMQ built with algorithm. The positions of a query are
+// written one after another.
+pub fn benchmark(
+    path: &Path,
+    algorithm: Algorithm,
+    k: Option<usize>,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    let instance = instances::stream_rmq_instance(path)?;
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    let rmq = algorithm.build(instance.numbers);
+
+    let algo = match k {
+        Some(_) => "top-k",
+        None => "all-minima",
+    };
+    let mut measurement = report::Measurement::new(algo, start.elapsed());
+
+    for (i, query) in instance.queries.enumerate() {
+        let (from, to) = query?;
+
+        // The instance only has ranges inside the numbers.
+        let start = Instant::now();
+        let positions: Vec<usize> = match k {
+            Some(k) => rmq
+                .top_k(from, to, k)
+                .unwrap()
+                .into_iter()
+                .map(|(position, _)| position)
+                .collect(),
+            None => rmq.all_minimum_positions(from, to).unwrap(),
+        };
+        measurement.record_query(start.elapsed());
+
+        for position in positions {
+            writer.push(position as u64);
+        }
+
+        if i % 100 == 0 {
+            debug!("Query nr {}", i);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = rmq.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::ValueEnum;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::rmq::Algorithm;
+
+    #[test]
+    fn testing_top_k() {
+        let numbers = vec![5, 1, 3, 1, 8, 7, 2];
+
+        for algorithm in Algorithm::value_variants() {
+            let rmq = algorithm.build(numbers.clone());
+
+            assert_eq!(Ok(vec![(1, 1), (3, 1), (6, 2), (2, 3)]), rmq.top_k(0, 6, 4));
+            assert_eq!(Ok(vec![(3, 1), (2, 3)]), rmq.top_k(2, 4, 2));
+            assert_eq!(Ok(vec![(4, 8)]), rmq.top_k(4, 4, 10));
+            assert_eq!(Ok(vec![]), rmq.top_k(0, 6, 0));
+            assert_eq!(Err(RMQError::OutOfRange), rmq.top_k(3, 7, 1));
+
+            assert_eq!(Ok(vec![1, 3]), rmq.all_minimum_positions(0, 6));
+            assert_eq!(Ok(vec![3]), rmq.all_minimum_positions(2, 6));
+            assert_eq!(Err(RMQError::OutOfRange), rmq.all_minimum_positions(4, 2));
+        }
+    }
+
+    #[test]
+    fn testing_top_k_benchmark() {
+        let path = std::env::temp_dir().join(format!("rmq_project_top_k_{}", std::process::id()));
+        let out = || {
+            Some(report::Output {
+                path: path.to_str().unwrap().to_string(),
+                format: report::OutputFormat::Lines,
+            })
+        };
+        let instance = Path::new("testdata/rmq_examples/rmq_example_2.txt");
+
+        // One position per query, and at least one for all minima.
+        benchmark(instance, Algorithm::Sparse, Some(1), out()).unwrap();
+        assert_eq!(
+            2000,
+            std::fs::read_to_string(&path).unwrap().lines().count()
+        );
+
+        benchmark(instance, Algorithm::SpanningBlocks, None, out()).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().lines().count() > 2000);
+    }
+
+    #[test]
+    fn testing_top_k_random() {
+        let mut rng = StdRng::seed_from_u64(6);
+
+        for _ in 0..100 {
+            let n = rng.gen_range(1..80);
+            let max_value = rng.gen_range(0..20);
+            let numbers: Vec<u64> = (0..n).map(|_| rng.gen_range(0..=max_value)).collect();
+
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n);
+            let (from, to) = (a.min(b), a.max(b));
+            let k = rng.gen_range(0..=n);
+
+            let mut sorted = numbers[from..=to].to_vec();
+            sorted.sort_unstable();
+            sorted.truncate(k);
+
+            let minimum = sorted.first().copied().unwrap_or(numbers[from]);
+            let want_positions: Vec<usize> =
+                (from..=to).filter(|i| numbers[*i] == minimum).collect();
+
+            for algorithm in Algorithm::value_variants() {
+                let rmq = algorithm.build(numbers.clone());
+
+                let got = rmq.top_k(from, to, k).unwrap();
+                let values: Vec<u64> = got.iter().map(|(_, number)| *number).collect();
+                assert_eq!(sorted, values, "{:?} {:?}", algorithm, numbers);
+
+                let mut positions: Vec<usize> = got.iter().map(|(i, _)| *i).collect();
+                assert!(got.iter().all(|(i, number)| numbers[*i] == *number));
+                positions.sort_unstable();
+                positions.dedup();
+                assert_eq!(got.len(), positions.len());
+                assert!(positions.iter().all(|i| (from..=to).contains(i)));
+
+                if k > 0 {
+                    assert_eq!(
+                        Ok(want_positions.clone()),
+                        rmq.all_minimum_positions(from, to)
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::heapsize;
+use super::report;
+use super::rmq::{Algorithm, RMQError, RMQ};
+
+use crate::instances::InstanceError;
+use crate::malloc_size_of::MallocSizeOf;
+use crate::malloc_size_of::MallocSizeOfOps;
+use log::{debug, info, trace};
+
+// Suffix array of a byte text with its inverse and the LCP array.
+#[derive(MallocSizeOf)]
+pub struct SuffixArray {
+    // Start positions of the suffixes in lexicographic order.
+    sa: Vec<usize>,
+    // rank[sa[r]] = r.
+    rank: Vec<usize>,
+    // lcp[r] is the longest common prefix of the suffixes sa[r - 1] and
+    // sa[r], lcp[0] = 0.
+    lcp: Vec<u64>,
+}
+
+impl SuffixArray {
+    // Prefix doubling: after round k the suffixes are sorted by their first
+    // 2^k bytes. O(n log^2 n).
+    pub fn new(text: &[u8]) -> Self {
+        let n = text.len();
+
+        let mut sa: Vec<usize> = (0..n).collect();
+        // Shorter suffixes are smaller, so missing bytes rank before every
+        // byte.
+        let mut rank: Vec<usize> = text.iter().map(|byte| *byte as usize + 1).collect();
+        let mut next = vec![0usize; n];
+
+        let mut k = 1;
+        loop {
+            let key = |i: usize| (rank[i], if i + k < n { rank[i + k] } else { 0 });
+
+            sa.sort_unstable_by_key(|i| key(*i));
+
+            // Equal keys share a rank.
+            for r in 0..n {
+                next[sa[r]] = match r {
+                    0 => 1,
+                    _ => next[sa[r - 1]] + (key(sa[r - 1]) != key(sa[r])) as usize,
+                };
+            }
+            std::mem::swap(&mut rank, &mut next);
+
+            debug!("Suffix array round k: {}", k);
+
+            // All ranks distinct, sorted completely.
+            if n == 0 || rank[sa[n - 1]] == n || k >= n {
+                break;
+            }
+            k *= 2;
+        }
+
+        // Ranks are 1-based while sorting.
+        for r in rank.iter_mut() {
+            *r -= 1;
+        }
+
+        let lcp = Self::kasai(text, &sa, &rank);
+
+        Self { sa, rank, lcp }
+    }
+
+    // LCP array in linear time: going through the suffixes in text order,
+    // the common prefix with the lexicographic predecessor shrinks by at most
+    // one from one suffix to the next.
+    fn kasai(text: &[u8], sa: &[usize], rank: &[usize]) -> Vec<u64> {
+        let n = text.len();
+        let mut lcp = vec![0u64; n];
+
+        let mut h = 0;
+        for i in 0..n {
+            if rank[i] == 0 {
+                h = 0;
+                continue;
+            }
+
+            let j = sa[rank[i] - 1];
+            while i + h < n && j + h < n && text[i + h] == text[j + h] {
+                h += 1;
+            }
+
+            lcp[rank[i]] = h as u64;
+            h = h.saturating_sub(1);
+        }
+
+        lcp
+    }
+
+    #[allow(dead_code)]
+    pub fn suffixes(&self) -> &[usize] {
+        &self.sa
+    }
+
+    #[allow(dead_code)]
+    pub fn rank(&self, i: usize) -> usize {
+        self.rank[i]
+    }
+
+    #[allow(dead_code)]
+    pub fn lcp(&self) -> &[u64] {
+        &self.lcp
+    }
+}
+
+// Longest common extension: the length of the longest common prefix of the
+// suffixes at i and j. It is the minimum of the LCP array between their
+// ranks.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(MallocSizeOf)]
+pub struct LCE {
+    suffix_array: SuffixArray,
+    // Over the LCP array.
+    rmq: Box<dyn RMQ>,
+}
+
+impl LCE {
+    pub fn new(text: &[u8], algorithm: Algorithm) -> Self {
+        let suffix_array = SuffixArray::new(text);
+        let rmq = algorithm.build(suffix_array.lcp.clone());
+
+        Self { suffix_array, rmq }
+    }
+
+    pub fn lce(&self, i: usize, j: usize) -> Result<u64, RMQError> {
+        let n = self.suffix_array.sa.len();
+        if i >= n || j >= n {
+            return Err(RMQError::OutOfRange);
+        }
+
+        // The whole suffix.
+        if i == j {
+            return Ok((n - i) as u64);
+        }
+
+        let a = self.suffix_array.rank[i];
+        let b = self.suffix_array.rank[j];
+
+        let position = self.rmq.range_minimum_query(a.min(b) + 1, a.max(b))?;
+
+        trace!("lce({}, {}): ranks {} {} -> {}", i, j, a, b, position);
+
+        Ok(self.suffix_array.lcp[position])
+    }
+}
+
+// Compares byte by byte. Oracle for LCE.
+pub fn lce_naive(text: &[u8], i: usize, j: usize) -> u64 {
+    text[i..]
+        .iter()
+        .zip(&text[j..])
+        .take_while(|(a, b)| a == b)
+        .count() as u64
+}
+
+// Random pairs of positions of a text of length n, none if it is empty.
+fn random_queries(n: usize, queries: usize, seed: u64) -> Vec<(usize, usize)> {
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..queries)
+        .map(|_| (rng.gen_range(0..n), rng.gen_range(0..n)))
+        .collect()
+}
+
+fn read_text(path: &Path) -> Result<Vec<u8>, InstanceError> {
+    let text = fs::read(path).map_err(|error| InstanceError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    debug!("Read text of {} bytes", text.len());
+
+    Ok(text)
+}
+
+// Benchmarks LCE on the bytes of the file at path with random queries.
+// Construction includes the suffix array and the LCP array.
+pub fn benchmark(
+    path: &Path,
+    algorithm: Algorithm,
+    queries: usize,
+    seed: u64,
+    out: Option<report::Output>,
+) -> Result<(), InstanceError> {
+    info!("lce");
+
+    let text = read_text(path)?;
+    let queries = random_queries(text.len(), queries, seed);
+
+    let mut writer = report::AnswerWriter::new(out);
+
+    let start = Instant::now();
+
+    let lce = LCE::new(&text, algorithm);
+
+    let mut measurement = report::Measurement::new("lce", start.elapsed());
+
+    for (k, (i, j)) in queries.iter().enumerate() {
+        let start = Instant::now();
+        let got = lce.lce(*i, *j).unwrap();
+        measurement.record_query(start.elapsed());
+
+        writer.push(got);
+
+        if k % 100 == 0 {
+            debug!("Query nr {}", k);
+        }
+    }
+
+    let mut ops = MallocSizeOfOps::new(heapsize::platform::usable_size, None, None);
+    measurement.space = lce.size_of(&mut ops);
+
+    writer.finish();
+
+    report::report(&measurement);
+
+    Ok(())
+}
+
+// Answers the same random queries as benchmark with LCE and lce_naive.
+// Returns the number of mismatches, the first few are printed.
+pub fn check_against_naive(
+    path: &Path,
+    algorithm: Algorithm,
+    queries: usize,
+    seed: u64,
+) -> Result<u64, InstanceError> {
+    let text = read_text(path)?;
+    let queries = random_queries(text.len(), queries, seed);
+
+    let lce = LCE::new(&text, algorithm);
+
+    let mut mismatches = 0;
+
+    for (k, (i, j)) in queries.iter().enumerate() {
+        let want = lce_naive(&text, *i, *j);
+        let got = lce.lce(*i, *j);
+
+        if got != Ok(want) {
+            if mismatches < 10 {
+                eprintln!(
+                    "Mismatch in query nr {}: {},{} want: {} got: {:?}",
+                    k, i, j, want, got
+                );
+            }
+            mismatches += 1;
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::ValueEnum;
+
+    #[test]
+    fn testing_suffix_array() {
+        let suffix_array = SuffixArray::new(b"banana");
+
+        // a, ana, anana, banana, na, nana
+        assert_eq!(&[5, 3, 1, 0, 4, 2], suffix_array.suffixes());
+        assert_eq!(&[0, 1, 3, 0, 0, 2], suffix_array.lcp());
+        assert_eq!(3, suffix_array.rank(0));
+
+        let mut rng = StdRng::seed_from_u64(9);
+
+        for n in [0, 1, 2, 17, 300] {
+            for alphabet in [1, 2, 4, 256] {
+                let text: Vec<u8> = (0..n).map(|_| rng.gen_range(0..alphabet) as u8).collect();
+                let suffix_array = SuffixArray::new(&text);
+
+                let mut want: Vec<usize> = (0..n).collect();
+                want.sort_by_key(|i| &text[*i..]);
+                assert_eq!(want, suffix_array.suffixes());
+
+                for r in 1..n {
+                    assert_eq!(
+                        lce_naive(&text, want[r - 1], want[r]),
+                        suffix_array.lcp()[r]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn testing_lce_all_algorithms() {
+        let mut rng = StdRng::seed_from_u64(10);
+
+        for text in [b"mississippi".to_vec(), vec![7; 50], vec![]] {
+            for algorithm in Algorithm::value_variants() {
+                let lce = LCE::new(&text, *algorithm);
+
+                for i in 0..text.len() {
+                    for j in 0..text.len() {
+                        assert_eq!(Ok(lce_naive(&text, i, j)), lce.lce(i, j), "{:?}", algorithm);
+                    }
+                }
+
+                assert_eq!(Err(RMQError::OutOfRange), lce.lce(0, text.len()));
+            }
+        }
+
+        let text: Vec<u8> = (0..2000).map(|_| rng.gen_range(b'a'..=b'c')).collect();
+        let lce = LCE::new(&text, Algorithm::SpanningBlocks);
+        for (i, j) in random_queries(text.len(), 2000, 1) {
+            assert_eq!(Ok(lce_naive(&text, i, j)), lce.lce(i, j));
+        }
+    }
+}
@@ -0,0 +1,29 @@
+mod wavelet_matrix;
+mod wavelet_tree;
+
+#[allow(unused_imports)]
+pub use wavelet_matrix::WaveletMatrix;
+#[allow(unused_imports)]
+pub use wavelet_tree::WaveletTree;
+
+use crate::bitvector::Bitvector;
+
+// Number of bits needed to represent every symbol up to max.
+//
+// At least 1, so a sequence of only zeroes still gets a level.
+fn bits_for(max: u64) -> u32 {
+    std::cmp::max(1, 64 - max.leading_zeros())
+}
+
+// Position of the k-th (1-based) bit equal to bit in level.
+//
+// The caller guarantees there are at least k such bits.
+fn select_bit(level: &Bitvector, bit: bool, k: u64) -> u64 {
+    let position = if bit {
+        level.select1(k)
+    } else {
+        level.select0(k)
+    };
+
+    position.expect("fewer matching bits in level than counted")
+}
@@ -0,0 +1,325 @@
+use crate::bitvector::Bitvector;
+
+use super::bits_for;
+
+// Wavelet matrix.
+//
+// Like the levelwise wavelet tree, every level is one bitvector of length n.
+// But instead of keeping the nodes of a level apart, each level stably moves
+// all symbols with a 0-bit to the front and all with a 1-bit to the back.
+// A range [a, b) on one level then maps to a single range on the next level
+// using only rank and the number of zeroes of the level.
+#[derive(MallocSizeOf, Clone)]
+pub struct WaveletMatrix {
+    len: u64,
+    // Bits per symbol, which is also the number of levels.
+    bits: u32,
+    // levels[l] holds bit (bits - 1 - l) of every symbol.
+    levels: Vec<Bitvector>,
+    // Number of 0s in each level, which is where the 1s start on the next.
+    zeros: Vec<u64>,
+}
+
+#[allow(dead_code)]
+impl WaveletMatrix {
+    pub fn new(sequence: &[u64]) -> Self {
+        let len = sequence.len() as u64;
+
+        if sequence.is_empty() {
+            return Self {
+                len,
+                bits: 0,
+                levels: Vec::new(),
+                zeros: Vec::new(),
+            };
+        }
+
+        let bits = bits_for(*sequence.iter().max().unwrap());
+
+        let mut levels: Vec<Bitvector> = Vec::with_capacity(bits as usize);
+        let mut zeros: Vec<u64> = Vec::with_capacity(bits as usize);
+        let mut current: Vec<u64> = sequence.to_vec();
+
+        for level in 0..bits {
+            let shift = bits - 1 - level;
+
+            let level_bits: Vec<bool> = current.iter().map(|v| (v >> shift) & 1 == 1).collect();
+            zeros.push(level_bits.iter().filter(|bit| !**bit).count() as u64);
+            levels.push(Bitvector::new(level_bits));
+
+            // Stable partition: all 0s to the front, all 1s to the back.
+            current.sort_by_key(|v| (v >> shift) & 1);
+        }
+
+        Self {
+            len,
+            bits,
+            levels,
+            zeros,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Bit of symbol on the given level.
+    fn bit(&self, symbol: u64, level: usize) -> bool {
+        (symbol >> (self.bits as usize - 1 - level)) & 1 == 1
+    }
+
+    // Whether symbol can occur at all.
+    fn in_alphabet(&self, symbol: u64) -> bool {
+        self.bits > 0 && bits_for(symbol) <= self.bits
+    }
+
+    // Range [a, b) on the next level after following the 0- or 1-child.
+    fn child(&self, level: usize, a: u64, b: u64, bit: bool) -> (u64, u64) {
+        let bitvector = &self.levels[level];
+
+        if bit {
+            (
+                self.zeros[level] + bitvector.rank1(a),
+                self.zeros[level] + bitvector.rank1(b),
+            )
+        } else {
+            (bitvector.rank0(a), bitvector.rank0(b))
+        }
+    }
+
+    fn check_range(&self, from: u64, to: u64) {
+        assert!(
+            from <= to && to < self.len,
+            "range [{}, {}] out of range len={}",
+            from,
+            to,
+            self.len
+        );
+    }
+
+    // Symbol at position i.
+    pub fn access(&self, i: u64) -> u64 {
+        assert!(i < self.len, "i={} out of range len={}", i, self.len);
+
+        let mut position = i;
+        let mut symbol = 0;
+
+        for level in 0..self.levels.len() {
+            let bit = self.levels[level].get(position);
+
+            symbol = (symbol << 1) | bit as u64;
+            position = self.child(level, position, position + 1, bit).0;
+        }
+
+        symbol
+    }
+
+    // k-th smallest (0-based) symbol in positions [from, to].
+    pub fn range_quantile(&self, from: u64, to: u64, k: u64) -> Option<u64> {
+        self.check_range(from, to);
+
+        if k > to - from {
+            return None;
+        }
+
+        let mut a = from;
+        let mut b = to + 1;
+        let mut k = k;
+        let mut symbol = 0;
+
+        for level in 0..self.levels.len() {
+            let zeros_in_range = self.levels[level].rank0(b) - self.levels[level].rank0(a);
+
+            let bit = k >= zeros_in_range;
+            if bit {
+                k -= zeros_in_range;
+            }
+
+            symbol = (symbol << 1) | bit as u64;
+            (a, b) = self.child(level, a, b, bit);
+        }
+
+        Some(symbol)
+    }
+
+    // Smallest symbol >= x in positions [from, to].
+    pub fn range_next_value(&self, from: u64, to: u64, x: u64) -> Option<u64> {
+        self.check_range(from, to);
+
+        if !self.in_alphabet(x) {
+            return None;
+        }
+
+        self.next_value(0, from, to + 1, x, 0)
+    }
+
+    // Walks down along x as long as the range is non-empty. Whenever x has
+    // a 0-bit, the 1-child is the fallback: everything in there is bigger
+    // than x, so its minimum is the answer if the 0-child has none.
+    fn next_value(&self, level: usize, a: u64, b: u64, x: u64, prefix: u64) -> Option<u64> {
+        if a == b {
+            return None;
+        }
+
+        if level == self.levels.len() {
+            return Some(prefix);
+        }
+
+        if self.bit(x, level) {
+            let (a1, b1) = self.child(level, a, b, true);
+            return self.next_value(level + 1, a1, b1, x, (prefix << 1) | 1);
+        }
+
+        let (a0, b0) = self.child(level, a, b, false);
+        if let Some(found) = self.next_value(level + 1, a0, b0, x, prefix << 1) {
+            return Some(found);
+        }
+
+        let (a1, b1) = self.child(level, a, b, true);
+        self.minimum(level + 1, a1, b1, (prefix << 1) | 1)
+    }
+
+    // Smallest symbol in [a, b) of the given level, with the bits above
+    // already fixed to prefix.
+    fn minimum(&self, level: usize, a: u64, b: u64, prefix: u64) -> Option<u64> {
+        if a == b {
+            return None;
+        }
+
+        let mut a = a;
+        let mut b = b;
+        let mut symbol = prefix;
+
+        for l in level..self.levels.len() {
+            let has_zero = self.levels[l].rank0(b) > self.levels[l].rank0(a);
+
+            symbol = (symbol << 1) | !has_zero as u64;
+            (a, b) = self.child(l, a, b, !has_zero);
+        }
+
+        Some(symbol)
+    }
+
+    // Number of positions in [from, to] holding a symbol in [lower, upper].
+    //
+    // Positions and symbols as the two axes make this a 2D orthogonal range
+    // count over the points (i, sequence[i]).
+    pub fn range_count(&self, from: u64, to: u64, lower: u64, upper: u64) -> u64 {
+        self.check_range(from, to);
+
+        if lower > upper {
+            return 0;
+        }
+
+        let below_upper = match upper.checked_add(1) {
+            Some(bound) => self.count_less(from, to + 1, bound),
+            None => to + 1 - from,
+        };
+
+        below_upper - self.count_less(from, to + 1, lower)
+    }
+
+    // Number of positions in [a, b) holding a symbol smaller than bound.
+    fn count_less(&self, a: u64, b: u64, bound: u64) -> u64 {
+        if !self.in_alphabet(bound) {
+            // Every symbol is smaller.
+            return b - a;
+        }
+
+        let mut a = a;
+        let mut b = b;
+        let mut count = 0;
+
+        for level in 0..self.levels.len() {
+            let bit = self.bit(bound, level);
+
+            if bit {
+                // Everything taking the 0-child is smaller than bound.
+                count += self.levels[level].rank0(b) - self.levels[level].rank0(a);
+            }
+
+            (a, b) = self.child(level, a, b, bit);
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WaveletMatrix;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn testing_wavelet_matrix_range_queries() {
+        let mut rng = StdRng::from_seed([0; 32]);
+
+        let sequence: Vec<u64> = (0..400).map(|_| rng.gen_range(0..100)).collect();
+        let matrix = WaveletMatrix::new(&sequence);
+
+        for (i, value) in sequence.iter().enumerate() {
+            assert_eq!(*value, matrix.access(i as u64), "access i={}", i);
+        }
+
+        for _ in 0..500 {
+            let from = rng.gen_range(0..sequence.len());
+            let to = rng.gen_range(from..sequence.len());
+
+            let mut sorted = sequence[from..=to].to_vec();
+            sorted.sort();
+
+            for (k, value) in sorted.iter().enumerate() {
+                assert_eq!(
+                    Some(*value),
+                    matrix.range_quantile(from as u64, to as u64, k as u64)
+                );
+            }
+            assert_eq!(
+                None,
+                matrix.range_quantile(from as u64, to as u64, sorted.len() as u64)
+            );
+
+            for x in 0..130 {
+                let want = sorted.iter().find(|v| **v >= x).copied();
+                assert_eq!(
+                    want,
+                    matrix.range_next_value(from as u64, to as u64, x),
+                    "range [{}, {}] x={}",
+                    from,
+                    to,
+                    x
+                );
+            }
+
+            let lower = rng.gen_range(0..110);
+            let upper = rng.gen_range(0..110);
+            let want = sorted
+                .iter()
+                .filter(|v| **v >= lower && **v <= upper)
+                .count() as u64;
+
+            assert_eq!(
+                want,
+                matrix.range_count(from as u64, to as u64, lower, upper)
+            );
+        }
+    }
+
+    #[test]
+    fn testing_wavelet_matrix_small() {
+        let matrix = WaveletMatrix::new(&[5, 5, 5]);
+
+        assert_eq!(Some(5), matrix.range_next_value(0, 2, 0));
+        assert_eq!(Some(5), matrix.range_next_value(1, 1, 5));
+        assert_eq!(None, matrix.range_next_value(0, 2, 6));
+        assert_eq!(None, matrix.range_next_value(0, 2, u64::MAX));
+        assert_eq!(Some(5), matrix.range_quantile(0, 2, 2));
+        assert_eq!(3, matrix.range_count(0, 2, 0, u64::MAX));
+    }
+}
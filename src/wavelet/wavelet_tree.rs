@@ -0,0 +1,389 @@
+use crate::bitvector::Bitvector;
+
+use super::bits_for;
+use super::select_bit;
+
+// Pointerless (levelwise) wavelet tree.
+//
+// Instead of one bitvector per node, all nodes of a level are concatenated
+// into a single bitvector of length n. A node is then just a range
+// [start, end) inside its level, and the children's ranges follow from
+// rank0 at the node borders.
+#[derive(MallocSizeOf, Clone)]
+pub struct WaveletTree {
+    len: u64,
+    // Bits per symbol, which is also the number of levels.
+    bits: u32,
+    // levels[l] holds bit (bits - 1 - l) of every symbol. The symbols on
+    // level l are stably ordered by their l highest bits, so every node is
+    // contiguous.
+    levels: Vec<Bitvector>,
+}
+
+#[allow(dead_code)]
+impl WaveletTree {
+    pub fn new(sequence: &[u64]) -> Self {
+        let len = sequence.len() as u64;
+
+        if sequence.is_empty() {
+            return Self {
+                len,
+                bits: 0,
+                levels: Vec::new(),
+            };
+        }
+
+        let bits = bits_for(*sequence.iter().max().unwrap());
+
+        let mut levels: Vec<Bitvector> = Vec::with_capacity(bits as usize);
+        let mut current: Vec<u64> = sequence.to_vec();
+
+        for level in 0..bits {
+            let shift = bits - 1 - level;
+
+            levels.push(Bitvector::new(
+                current.iter().map(|v| (v >> shift) & 1 == 1).collect(),
+            ));
+
+            // Splitting every node stably into its 0- and 1-child is the
+            // same as stably sorting by the level + 1 highest bits, because
+            // current is already sorted by the level highest bits.
+            current.sort_by_key(|v| v >> shift);
+        }
+
+        Self { len, bits, levels }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Bit of symbol on the given level.
+    fn bit(&self, symbol: u64, level: u32) -> bool {
+        (symbol >> (self.bits - 1 - level)) & 1 == 1
+    }
+
+    // Whether symbol can occur at all.
+    fn in_alphabet(&self, symbol: u64) -> bool {
+        self.bits > 0 && bits_for(symbol) <= self.bits
+    }
+
+    // Symbol at position i.
+    pub fn access(&self, i: u64) -> u64 {
+        assert!(i < self.len, "i={} out of range len={}", i, self.len);
+
+        let mut start = 0;
+        let mut end = self.len;
+        let mut position = i;
+        let mut symbol = 0;
+
+        for level in &self.levels {
+            let zeros_before_node = level.rank0(start);
+            let zeros_in_node = level.rank0(end) - zeros_before_node;
+
+            if level.get(position) {
+                symbol = (symbol << 1) | 1;
+
+                let ones_before = level.rank1(position) - level.rank1(start);
+                start += zeros_in_node;
+                position = start + ones_before;
+            } else {
+                symbol <<= 1;
+
+                let zeros_before = level.rank0(position) - zeros_before_node;
+                end = start + zeros_in_node;
+                position = start + zeros_before;
+            }
+        }
+
+        symbol
+    }
+
+    // Number of occurrences of symbol in [0, i).
+    pub fn rank(&self, symbol: u64, i: u64) -> u64 {
+        assert!(i <= self.len, "i={} out of range len={}", i, self.len);
+
+        if !self.in_alphabet(symbol) {
+            return 0;
+        }
+
+        let mut start = 0;
+        let mut end = self.len;
+        let mut position = i;
+
+        for (l, level) in self.levels.iter().enumerate() {
+            let zeros_before_node = level.rank0(start);
+            let zeros_in_node = level.rank0(end) - zeros_before_node;
+
+            if self.bit(symbol, l as u32) {
+                let ones_before = level.rank1(position) - level.rank1(start);
+                start += zeros_in_node;
+                position = start + ones_before;
+            } else {
+                let zeros_before = level.rank0(position) - zeros_before_node;
+                end = start + zeros_in_node;
+                position = start + zeros_before;
+            }
+        }
+
+        position - start
+    }
+
+    // Position of the k-th occurrence of symbol.
+    //
+    // 1-based like Bitvector::select1, so select(c, 1) is the first c.
+    pub fn select(&self, symbol: u64, k: u64) -> Option<u64> {
+        if k == 0 || !self.in_alphabet(symbol) {
+            return None;
+        }
+
+        // Start of the node of symbol on every level, to walk back up.
+        let mut starts: Vec<u64> = Vec::with_capacity(self.levels.len());
+
+        let mut start = 0;
+        let mut end = self.len;
+
+        for (l, level) in self.levels.iter().enumerate() {
+            starts.push(start);
+
+            let zeros_in_node = level.rank0(end) - level.rank0(start);
+
+            if self.bit(symbol, l as u32) {
+                start += zeros_in_node;
+            } else {
+                end = start + zeros_in_node;
+            }
+        }
+
+        if k > end - start {
+            return None;
+        }
+
+        // Offset inside the leaf, then inside each parent.
+        let mut offset = k - 1;
+
+        for l in (0..self.levels.len()).rev() {
+            let level = &self.levels[l];
+            let bit = self.bit(symbol, l as u32);
+
+            let before_node = if bit {
+                level.rank1(starts[l])
+            } else {
+                level.rank0(starts[l])
+            };
+
+            offset = select_bit(level, bit, before_node + offset + 1) - starts[l];
+        }
+
+        Some(offset)
+    }
+
+    // k-th smallest (0-based) symbol in positions [from, to].
+    pub fn range_quantile(&self, from: u64, to: u64, k: u64) -> Option<u64> {
+        assert!(
+            from <= to && to < self.len,
+            "range [{}, {}] out of range len={}",
+            from,
+            to,
+            self.len
+        );
+
+        if k > to - from {
+            return None;
+        }
+
+        let mut start = 0;
+        let mut end = self.len;
+        let mut a = from;
+        let mut b = to + 1;
+        let mut k = k;
+        let mut symbol = 0;
+
+        for level in &self.levels {
+            let zeros_before_node = level.rank0(start);
+            let zeros_in_node = level.rank0(end) - zeros_before_node;
+            let zeros_before_a = level.rank0(a) - zeros_before_node;
+            let zeros_before_b = level.rank0(b) - zeros_before_node;
+            let zeros_in_range = zeros_before_b - zeros_before_a;
+
+            if k < zeros_in_range {
+                symbol <<= 1;
+
+                end = start + zeros_in_node;
+                a = start + zeros_before_a;
+                b = start + zeros_before_b;
+            } else {
+                symbol = (symbol << 1) | 1;
+                k -= zeros_in_range;
+
+                let ones_before_a = (a - start) - zeros_before_a;
+                let ones_before_b = (b - start) - zeros_before_b;
+                start += zeros_in_node;
+                a = start + ones_before_a;
+                b = start + ones_before_b;
+            }
+        }
+
+        Some(symbol)
+    }
+
+    // Number of positions in [from, to] holding a symbol in [lower, upper].
+    pub fn range_count(&self, from: u64, to: u64, lower: u64, upper: u64) -> u64 {
+        assert!(
+            from <= to && to < self.len,
+            "range [{}, {}] out of range len={}",
+            from,
+            to,
+            self.len
+        );
+
+        if lower > upper {
+            return 0;
+        }
+
+        let below_upper = match upper.checked_add(1) {
+            Some(bound) => self.count_less(from, to + 1, bound),
+            None => to + 1 - from,
+        };
+
+        below_upper - self.count_less(from, to + 1, lower)
+    }
+
+    // Number of positions in [a, b) holding a symbol smaller than bound.
+    fn count_less(&self, a: u64, b: u64, bound: u64) -> u64 {
+        if !self.in_alphabet(bound) {
+            // Every symbol is smaller.
+            return b - a;
+        }
+
+        let mut start = 0;
+        let mut end = self.len;
+        let mut a = a;
+        let mut b = b;
+        let mut count = 0;
+
+        for (l, level) in self.levels.iter().enumerate() {
+            let zeros_before_node = level.rank0(start);
+            let zeros_in_node = level.rank0(end) - zeros_before_node;
+            let zeros_before_a = level.rank0(a) - zeros_before_node;
+            let zeros_before_b = level.rank0(b) - zeros_before_node;
+
+            if self.bit(bound, l as u32) {
+                // The whole 0-child is smaller than bound.
+                count += zeros_before_b - zeros_before_a;
+
+                let ones_before_a = (a - start) - zeros_before_a;
+                let ones_before_b = (b - start) - zeros_before_b;
+                start += zeros_in_node;
+                a = start + ones_before_a;
+                b = start + ones_before_b;
+            } else {
+                end = start + zeros_in_node;
+                a = start + zeros_before_a;
+                b = start + zeros_before_b;
+            }
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WaveletTree;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_sequence(len: usize, sigma: u64) -> Vec<u64> {
+        let mut rng = StdRng::from_seed([0; 32]);
+
+        (0..len).map(|_| rng.gen_range(0..sigma)).collect()
+    }
+
+    #[test]
+    fn testing_wavelet_tree_access_rank_select() {
+        let sequence = random_sequence(500, 37);
+        let tree = WaveletTree::new(&sequence);
+
+        for (i, value) in sequence.iter().enumerate() {
+            assert_eq!(*value, tree.access(i as u64), "access i={}", i);
+        }
+
+        for symbol in 0..40 {
+            let positions: Vec<usize> = (0..sequence.len())
+                .filter(|i| sequence[*i] == symbol)
+                .collect();
+
+            for i in 0..=sequence.len() {
+                let want = positions.iter().filter(|p| **p < i).count() as u64;
+                assert_eq!(
+                    want,
+                    tree.rank(symbol, i as u64),
+                    "rank c={} i={}",
+                    symbol,
+                    i
+                );
+            }
+
+            for (k, position) in positions.iter().enumerate() {
+                assert_eq!(Some(*position as u64), tree.select(symbol, k as u64 + 1));
+            }
+            assert_eq!(None, tree.select(symbol, positions.len() as u64 + 1));
+        }
+    }
+
+    #[test]
+    fn testing_wavelet_tree_range_queries() {
+        let sequence = random_sequence(300, 20);
+        let tree = WaveletTree::new(&sequence);
+
+        let mut rng = StdRng::from_seed([1; 32]);
+
+        for _ in 0..500 {
+            let from = rng.gen_range(0..sequence.len());
+            let to = rng.gen_range(from..sequence.len());
+
+            let mut sorted = sequence[from..=to].to_vec();
+            sorted.sort();
+
+            for (k, value) in sorted.iter().enumerate() {
+                assert_eq!(
+                    Some(*value),
+                    tree.range_quantile(from as u64, to as u64, k as u64)
+                );
+            }
+            assert_eq!(
+                None,
+                tree.range_quantile(from as u64, to as u64, sorted.len() as u64)
+            );
+
+            let lower = rng.gen_range(0..25);
+            let upper = rng.gen_range(0..25);
+            let want = sorted
+                .iter()
+                .filter(|v| **v >= lower && **v <= upper)
+                .count() as u64;
+
+            assert_eq!(want, tree.range_count(from as u64, to as u64, lower, upper));
+        }
+    }
+
+    #[test]
+    fn testing_wavelet_tree_small() {
+        let tree = WaveletTree::new(&[0, 0]);
+        assert_eq!(0, tree.access(1));
+        assert_eq!(Some(1), tree.select(0, 2));
+        assert_eq!(2, tree.rank(0, 2));
+        assert_eq!(0, tree.rank(1, 2));
+        assert_eq!(2, tree.range_count(0, 1, 0, u64::MAX));
+
+        let tree = WaveletTree::new(&[]);
+        assert!(tree.is_empty());
+        assert_eq!(None, tree.select(0, 1));
+    }
+}